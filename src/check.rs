@@ -0,0 +1,209 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::{
+    BLANK,
+    error::{Diagnostic, Severity},
+    turd::{Pattern, Rule, SymbolClass, Turd, TurdFile},
+};
+
+impl<'a> TurdFile<'a> {
+    /// Lints the machine, reporting errors, warnings and notes in line order.
+    pub fn check(&self, filepath: &str, initial: Option<&str>) -> Vec<Diagnostic> {
+        let diagnostic = |turd: &Turd, span: &str, severity, message| {
+            let source = self.source.lines().nth(turd.line - 1).unwrap();
+            Diagnostic::new(filepath, turd.line, source, span, message).with_severity(severity)
+        };
+        let mut diagnostics = Vec::new();
+
+        let rules = self.rules();
+        let patterns = rules.iter().map(|rule| {
+            let read = rule
+                .read
+                .iter()
+                .map(|read| self.header.pattern(read).unwrap());
+            read.collect::<Vec<_>>()
+        });
+        let patterns = patterns.collect::<Vec<_>>();
+        let blank = self.header.blank.unwrap_or(BLANK);
+        // Symbols the machine is known to use, to find where two classes overlap.
+        let mut known = BTreeSet::from([blank]);
+        known.extend(self.header.alphabet.iter().flatten());
+        known.extend(self.header.sets.iter().flat_map(|(_, set)| set));
+        known.extend(patterns.iter().flatten().filter_map(|p| match p {
+            Pattern::Literal(symbol) => Some(*symbol),
+            _ => None,
+        }));
+        let written = rules
+            .iter()
+            .flat_map(|rule| &rule.write)
+            .map(String::as_str);
+        known.extend(written.filter(|&write| write != "*"));
+        // Range bounds are also tried, since two ranges that overlap always
+        // share the larger of their starts.
+        let bounds = |p: &Pattern| match p {
+            Pattern::Class(SymbolClass::Chars { ranges, .. }) => ranges.clone(),
+            _ => Vec::new(),
+        };
+        let overlap = |(a, p): (&str, &Pattern), (b, q): (&str, &Pattern)| match (p, q) {
+            (Pattern::Any, _) => Some(b.to_string()),
+            (_, Pattern::Any) => Some(a.to_string()),
+            (Pattern::Literal(symbol), other) | (other, Pattern::Literal(symbol)) => {
+                other.matches(symbol).then(|| symbol.to_string())
+            }
+            _ => (known.iter().map(|s| s.to_string()))
+                .chain(
+                    bounds(p)
+                        .into_iter()
+                        .chain(bounds(q))
+                        .map(|(s, _)| s.to_string()),
+                )
+                .find(|s| p.matches(s) && q.matches(s)),
+        };
+
+        let mut outgoing = HashMap::<&str, Vec<&Rule>>::new();
+        let mut conflicting = HashSet::new();
+        let mut shadowed = HashSet::new();
+        for (j, rule) in rules.iter().enumerate() {
+            outgoing.entry(&rule.current).or_default().push(rule);
+            for (i, earlier) in rules[..j].iter().enumerate() {
+                if earlier.current != rule.current {
+                    continue;
+                }
+                let reads = (0..rule.read.len()).map(|n| {
+                    overlap(
+                        (&earlier.read[n], &patterns[i][n]),
+                        (&rule.read[n], &patterns[j][n]),
+                    )
+                });
+                let Some(reads) = reads.collect::<Option<Vec<_>>>() else {
+                    continue;
+                };
+                let (turd, reads) = (rule.turd, reads.join(","));
+                let specificity = Pattern::specificity(&patterns[j]);
+                let earlier_specificity = Pattern::specificity(&patterns[i]);
+                if specificity == earlier_specificity {
+                    if !conflicting.insert(turd.line) {
+                        continue;
+                    }
+                    let message = match earlier.turd.line == turd.line {
+                        true => format!(
+                            "Expands to more than one transition for state {} reading {reads}, \
+                             and only the first fires unless run with --nondeterministic",
+                            rule.current
+                        ),
+                        false => format!(
+                            "Conflicts with the transition on line {} for state {} reading \
+                             {reads}, which always fires first unless run with \
+                             --nondeterministic",
+                            earlier.turd.line, rule.current
+                        ),
+                    };
+                    diagnostics.push(diagnostic(turd, turd.text, Severity::Warning, message));
+                    continue;
+                }
+                let (general, specific) = match specificity < earlier_specificity {
+                    true => (turd, earlier.turd),
+                    false => (earlier.turd, turd),
+                };
+                if shadowed.insert(general.line) {
+                    diagnostics.push(diagnostic(
+                        general,
+                        general.text,
+                        Severity::Note,
+                        format!(
+                            "The more specific transition on line {} takes precedence over \
+                             this one for state {} reading {reads}",
+                            specific.line, rule.current
+                        ),
+                    ));
+                }
+            }
+        }
+
+        if let Some(initial) = initial.or(self.header.initial) {
+            let mut reachable = HashSet::from([initial]);
+            let mut pending = vec![initial];
+            while let Some(state) = pending.pop() {
+                for rule in outgoing.get(state).into_iter().flatten() {
+                    if reachable.insert(&rule.next) {
+                        pending.push(&rule.next);
+                    }
+                }
+            }
+            let mut reported = HashSet::new();
+            for rule in &rules {
+                if !reachable.contains(rule.current.as_str()) && reported.insert(&rule.current) {
+                    diagnostics.push(diagnostic(
+                        rule.turd,
+                        rule.turd.current,
+                        Severity::Warning,
+                        format!("State {} is unreachable from {initial}", rule.current),
+                    ));
+                }
+            }
+        }
+
+        let halting = self
+            .header
+            .halting
+            .iter()
+            .map(|&(state, _)| state)
+            .collect::<HashSet<_>>();
+        let mut dead_ends = HashSet::new();
+        for rule in &rules {
+            if !outgoing.contains_key(rule.next.as_str())
+                && !halting.contains(rule.next.as_str())
+                && dead_ends.insert(&rule.next)
+            {
+                diagnostics.push(diagnostic(
+                    rule.turd,
+                    rule.turd.next,
+                    Severity::Warning,
+                    format!(
+                        "State {} has no transitions and is not declared in accept:, \
+                         reject: or halt:",
+                        rule.next
+                    ),
+                ));
+            }
+        }
+
+        let written = rules.iter().flat_map(|rule| &rule.write);
+        let written = written.map(String::as_str).collect::<HashSet<_>>();
+        let read = |symbol| patterns.iter().flatten().any(|p| p.matches(symbol));
+        let mut reported = HashSet::new();
+        for (rule, reads) in rules.iter().zip(&patterns) {
+            for (symbol, &span) in rule.write.iter().zip(&rule.turd.write) {
+                let symbol = symbol.as_str();
+                if symbol != blank && symbol != "*" && !read(symbol) && reported.insert(symbol) {
+                    diagnostics.push(diagnostic(
+                        rule.turd,
+                        span,
+                        Severity::Warning,
+                        format!("{symbol} is written but never read"),
+                    ));
+                }
+            }
+            for ((symbol, &span), pattern) in rule.read.iter().zip(&rule.turd.read).zip(reads) {
+                let symbol = symbol.as_str();
+                if let Pattern::Literal(_) = pattern
+                    && symbol != blank
+                    && !written.contains(symbol)
+                    && reported.insert(symbol)
+                {
+                    diagnostics.push(diagnostic(
+                        rule.turd,
+                        span,
+                        Severity::Note,
+                        format!(
+                            "{symbol} is read but never written, so it can only come from the input"
+                        ),
+                    ));
+                }
+            }
+        }
+
+        diagnostics.sort_by_key(|d| (d.line, d.column));
+        diagnostics
+    }
+}
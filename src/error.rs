@@ -0,0 +1,151 @@
+use std::{error::Error, fmt::Display, io, process::ExitCode};
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    /// Makes the file unusable.
+    Error,
+    /// Likely a mistake, though the machine still runs.
+    Warning,
+    /// Worth knowing, such as which of two overlapping transitions wins.
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+            Self::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A message about a line of a file, displayed rustc-style with an excerpt
+/// of the line and carets under the part it is about.
+#[derive(Debug)]
+pub struct Diagnostic {
+    severity: Severity,
+    file: String,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+    len: usize,
+    source: String,
+    message: String,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(file: &str, line: usize, source: &str, span: &str, message: String) -> Self {
+        let offset = span.as_ptr() as usize - source.as_ptr() as usize;
+        Self {
+            severity: Severity::Error,
+            file: file.to_string(),
+            line,
+            column: source[..offset].chars().count() + 1,
+            len: span.chars().count().max(1),
+            source: source.to_string(),
+            message,
+        }
+    }
+
+    pub(crate) fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    /// How serious the diagnostic is.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// The 1-based line the diagnostic is about.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// What the diagnostic says, without the excerpt.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let gutter = self.line.to_string().len();
+        // Keep tabs so the carets line up with the excerpt above them.
+        let padding = self
+            .source
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        writeln!(f, "{}: {}", self.severity, self.message)?;
+        writeln!(
+            f,
+            "{:gutter$}--> {}:{}:{}",
+            "", self.file, self.line, self.column
+        )?;
+        writeln!(f, "{:gutter$} |", "")?;
+        writeln!(f, "{} | {}", self.line, self.source)?;
+        writeln!(f, "{:gutter$} | {padding}{}", "", "^".repeat(self.len))
+    }
+}
+
+// A parse error pointing at the part of a line it is about, before the
+// caller turns it into a `Diagnostic` for that file and line.
+pub(crate) struct SpanError<'a> {
+    pub(crate) span: &'a str,
+    pub(crate) message: String,
+}
+
+/// Everything that can go wrong loading or running a machine.
+#[derive(Debug)]
+pub enum TuringMachineError {
+    /// Every error found in a file, so they can all be fixed at once.
+    Parse(Vec<Diagnostic>),
+    /// A step, tape mode or other value that could not be read.
+    Transformation(String),
+    /// A bad combination of options, states or inputs.
+    Args(String),
+    /// A head or write that the tape does not allow.
+    Tape(String),
+    /// A file that could not be read or output that could not be written.
+    Io(io::Error),
+}
+
+impl From<io::Error> for TuringMachineError {
+    fn from(v: io::Error) -> Self {
+        Self::Io(v)
+    }
+}
+
+impl Error for TuringMachineError {}
+
+impl TuringMachineError {
+    /// The sysexits.h code for the error: 64 for usage, 65 for data, 70 for
+    /// tape faults and 74 for I/O.
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Self::Args(_) => ExitCode::from(64),
+            Self::Parse(_) | Self::Transformation(_) => ExitCode::from(65),
+            Self::Tape(_) => ExitCode::from(70),
+            Self::Io(_) => ExitCode::from(74),
+        }
+    }
+}
+
+impl Display for TuringMachineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Parse(diagnostics) => {
+                diagnostics.iter().try_for_each(|d| writeln!(f, "{d}"))?;
+                match diagnostics.len() {
+                    1 => write!(f, "error: aborting due to 1 previous error"),
+                    n => write!(f, "error: aborting due to {n} previous errors"),
+                }
+            }
+            Self::Transformation(s) | Self::Args(s) | Self::Tape(s) => s.fmt(f),
+            Self::Io(error) => error.fmt(f),
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{
+    TuringMachineError,
+    error::{Diagnostic, SpanError},
+    turd::split_comment,
+};
+
+/// The starting contents of one tape. Multi-track cells are stored as their
+/// tracks joined with `|`, such as `1|a`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputTape {
+    /// The symbol of each cell, from the leftmost.
+    pub cells: Vec<String>,
+    /// The index of the cell marked with `^`, if any.
+    pub head: Option<usize>,
+}
+
+impl InputTape {
+    fn mark<'a>(&mut self, index: usize, token: &'a str) -> Result<&'a str, SpanError<'a>> {
+        match token.strip_prefix('^').filter(|symbol| !symbol.is_empty()) {
+            None => Ok(token),
+            Some(_) if self.head.is_some_and(|head| head != index) => Err(SpanError {
+                span: token,
+                message: "The head is already marked on this tape".to_string(),
+            }),
+            Some(symbol) => {
+                self.head = Some(index);
+                Ok(symbol)
+            }
+        }
+    }
+
+    fn extend<'a>(
+        &mut self,
+        tokens: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), SpanError<'a>> {
+        for token in tokens {
+            let symbol = self.mark(self.cells.len(), token)?;
+            self.cells.push(symbol.to_string());
+        }
+        Ok(())
+    }
+
+    fn add_track<'a>(&mut self, line: &'a str, tokens: &[&'a str]) -> Result<(), SpanError<'a>> {
+        if tokens.len() != self.cells.len() {
+            return Err(SpanError {
+                span: line,
+                message: format!(
+                    "Expected {} cells to line up with the tape, found {}",
+                    self.cells.len(),
+                    tokens.len()
+                ),
+            });
+        }
+        for (i, token) in tokens.iter().enumerate() {
+            let symbol = self.mark(i, token)?;
+            self.cells[i] = format!("{}|{symbol}", self.cells[i]);
+        }
+        Ok(())
+    }
+}
+
+/// What the `test` command checks once an input has run. Runs ignore it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Expected {
+    /// The [`RunResult::kind`](crate::RunResult::kind) the run should end with.
+    pub result: Option<String>,
+    /// The state the run should end in.
+    pub state: Option<String>,
+    /// The written cells of each tape, with `_` for the blank.
+    pub output: Option<Vec<String>>,
+    /// A step limit for the test, on top of any given on the command line.
+    pub max_steps: Option<u64>,
+}
+
+/// One run's worth of tapes from a tape file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Input {
+    /// The name given with `input:` or `test:`.
+    pub name: Option<String>,
+    /// The state to start in, given with `state:`.
+    pub state: Option<String>,
+    /// The contents of each tape, from the first.
+    pub tapes: Vec<InputTape>,
+    /// How a test of the input should end.
+    pub expected: Expected,
+}
+
+impl Input {
+    /// Plain files without directives hold the symbols of a single tape.
+    /// Otherwise `input:` starts a named input, `state:` sets its initial
+    /// state, `tape:` adds a tape and `track:` adds a track to the last tape.
+    /// `expect:`, `final:`, `output:` and `max-steps:` say how a test of the
+    /// input should end. `test:` starts an input like `input:` does, inside
+    /// a .turd file. In files with directives, `#` starts a comment at the
+    /// start of a line or after the argument of a directive, but not among
+    /// the symbols of `tape:`, `track:` and `output:`.
+    pub fn parse_file(filepath: &str, content: &str) -> Result<Vec<Self>, TuringMachineError> {
+        let structured = content
+            .lines()
+            .any(|line| (line.split_whitespace().next()).is_some_and(|token| token.ends_with(':')));
+        let mut inputs = vec![Self::default()];
+        if !structured {
+            inputs[0].tapes.push(InputTape::default());
+        }
+        let mut diagnostics = Vec::new();
+        for (i, source) in content.lines().enumerate() {
+            let line = source.trim();
+            if line.is_empty() || structured && line.starts_with('#') {
+                continue;
+            }
+            let result = match structured {
+                true => Self::parse_directive(&mut inputs, split_directive_comment(line).0),
+                false => inputs[0].tapes[0].extend(line.split_whitespace()),
+            };
+            if let Err(e) = result {
+                diagnostics.push(Diagnostic::new(filepath, i + 1, source, e.span, e.message));
+            }
+        }
+        if !diagnostics.is_empty() {
+            return Err(TuringMachineError::Parse(diagnostics));
+        }
+
+        Ok(inputs)
+    }
+
+    pub(crate) fn parse_directive<'a>(
+        inputs: &mut Vec<Self>,
+        line: &'a str,
+    ) -> Result<(), SpanError<'a>> {
+        let error = |span, message| SpanError { span, message };
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next().unwrap();
+        let args = tokens.collect::<Vec<_>>();
+        match keyword {
+            "input:" | "test:" | "state:" => {
+                let [value] = args[..] else {
+                    return Err(error(
+                        line,
+                        format!("{keyword} expects exactly one argument"),
+                    ));
+                };
+                if keyword == "state:" {
+                    let input = inputs.last_mut().unwrap();
+                    if input.state.replace(value.to_string()).is_some() {
+                        return Err(error(keyword, "state: is declared more than once".into()));
+                    }
+                } else if inputs
+                    .iter()
+                    .any(|input| input.name.as_deref() == Some(value))
+                {
+                    let kind = if keyword == "test:" { "Test" } else { "Input" };
+                    return Err(error(
+                        value,
+                        format!("{kind} {value} is declared more than once"),
+                    ));
+                } else if let Some(input) = inputs.last_mut()
+                    && input.name.is_none()
+                    && input.state.is_none()
+                    && input.tapes.is_empty()
+                    && input.expected.result.is_none()
+                    && input.expected.state.is_none()
+                    && input.expected.output.is_none()
+                    && input.expected.max_steps.is_none()
+                {
+                    input.name = Some(value.to_string());
+                } else {
+                    inputs.push(Self {
+                        name: Some(value.to_string()),
+                        ..Self::default()
+                    });
+                }
+            }
+            "tape:" => {
+                let mut tape = InputTape::default();
+                tape.extend(args)?;
+                inputs.last_mut().unwrap().tapes.push(tape);
+            }
+            "track:" => {
+                let Some(tape) = inputs.last_mut().unwrap().tapes.last_mut() else {
+                    return Err(error(keyword, "track: must follow a tape: line".into()));
+                };
+                tape.add_track(line, &args)?;
+            }
+            "expect:" | "final:" | "max-steps:" => {
+                let [value] = args[..] else {
+                    return Err(error(
+                        line,
+                        format!("{keyword} expects exactly one argument"),
+                    ));
+                };
+                let expected = &mut inputs.last_mut().unwrap().expected;
+                let duplicate = match keyword {
+                    "expect:" => {
+                        if !["accept", "reject", "halt", "stuck"].contains(&value) {
+                            return Err(error(
+                                value,
+                                format!(
+                                    "{value} is not a valid result. Expected 'accept', \
+                                     'reject', 'halt' or 'stuck'"
+                                ),
+                            ));
+                        }
+                        expected.result.replace(value.to_string()).is_some()
+                    }
+                    "final:" => expected.state.replace(value.to_string()).is_some(),
+                    _ => {
+                        let steps = value.parse().map_err(|_| {
+                            error(value, format!("{value} is not a number of steps"))
+                        })?;
+                        expected.max_steps.replace(steps).is_some()
+                    }
+                };
+                if duplicate {
+                    return Err(error(
+                        keyword,
+                        format!("{keyword} is declared more than once"),
+                    ));
+                }
+            }
+            "output:" => {
+                let output = args.iter().map(|cell| cell.to_string()).collect();
+                let expected = &mut inputs.last_mut().unwrap().expected;
+                if expected.output.replace(output).is_some() {
+                    return Err(error(keyword, "output: is declared more than once".into()));
+                }
+            }
+            _ => {
+                return Err(error(
+                    keyword,
+                    format!(
+                        "Expected input:, state:, tape:, track:, expect:, final:, output: or \
+                         max-steps: instead of {keyword}"
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Splits a trailing comment off a tape file directive. The symbols of
+// `tape:`, `track:` and `output:` can be `#`, so they never have one.
+pub(crate) fn split_directive_comment(line: &str) -> (&str, Option<&str>) {
+    let skip = match line.split_whitespace().next() {
+        Some("tape:" | "track:" | "output:") => usize::MAX,
+        _ => 2,
+    };
+    split_comment(line, skip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directives_take_trailing_comments() {
+        let content = "input: one # the first\nstate: q # starts here\ntape: 1 # 1\n";
+        let inputs = Input::parse_file("test.tape", content).unwrap();
+        assert_eq!(inputs[0].name.as_deref(), Some("one"));
+        assert_eq!(inputs[0].state.as_deref(), Some("q"));
+        assert_eq!(inputs[0].tapes[0].cells, ["1", "#", "1"]);
+    }
+}
@@ -0,0 +1,54 @@
+//! A Turing machine simulator.
+//!
+//! A machine is written as a `.turd` file of transitions, one per line:
+//! the current state, the symbol read, the symbol written, the head move
+//! and the next state. [`TurdFile::parse`] reads one, [`Program::compile`]
+//! turns it into lookup tables, and a [`Machine`] runs a program over tapes
+//! read with [`Input::parse_file`]. [`Machine::events`] steps through a
+//! run one [`Event`] at a time, telling which transition fired and what it
+//! did to each tape, and an [`Observer`] is told about each step while
+//! [`Machine::run`] runs.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use turing_machine::{Input, Limits, Machine, Program, TapeMode, TurdFile};
+//!
+//! let source = std::fs::read_to_string("add.turd")?;
+//! let file = TurdFile::parse("add.turd", &source)?;
+//! let program = Arc::new(Program::compile(&file));
+//! let input = &Input::parse_file("--tape", "1 1 + 1")?[0];
+//! let mut machine = Machine::new(program, TapeMode::Infinite, "q0", &input.tapes)?;
+//! let result = machine.run(Limits::default(), &mut ())?;
+//! println!("{result} after {} steps: {}", machine.steps(), machine.contents());
+//! # Ok::<(), turing_machine::TuringMachineError>(())
+//! ```
+
+#![warn(missing_docs)]
+
+mod check;
+mod error;
+mod input;
+mod machine;
+mod observer;
+mod program;
+mod tape;
+mod turd;
+
+pub use error::{Diagnostic, Severity, TuringMachineError};
+pub use input::{Expected, Input, InputTape};
+pub use machine::{Branch, Event, Events, Exploration, Limits, Machine, RunResult, TapeEvent};
+pub use observer::Observer;
+pub use program::Program;
+pub use tape::{LeftEdge, Step, TapeMode};
+pub use turd::{Dot, Halting, Turd, TurdFile};
+
+type State<'a> = &'a str;
+type Symbol<'a> = &'a str;
+type StateId = usize;
+type SymbolId = usize;
+
+/// The symbol of an unwritten cell, unless a machine declares another with
+/// `blank:`. In tape files it always stands for the machine's blank.
+pub const BLANK: &str = "_";
+const LEFT_END: Symbol<'static> = "<";
+const RIGHT_END: Symbol<'static> = ">";
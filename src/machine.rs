@@ -0,0 +1,776 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    io::{self, Write},
+    iter::FusedIterator,
+    process::ExitCode,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    BLANK, StateId, SymbolId, TuringMachineError,
+    input::InputTape,
+    observer::Observer,
+    program::{Program, Transition},
+    tape::{Step, Tape, TapeMode},
+    turd::Halting,
+};
+
+/// How a run ended.
+#[derive(Debug, PartialEq)]
+pub enum RunResult {
+    /// Reached an `accept:` state.
+    Accepted,
+    /// Reached a `reject:` state, or every branch of a nondeterministic run
+    /// died without accepting.
+    Rejected,
+    /// Reached the given `halt:` state.
+    Halted(String),
+    /// No transition matches in the given state and the symbols read, which
+    /// are joined with `,` on several tapes.
+    Stuck(String, String),
+    /// Took [`Limits::max_steps`] steps without stopping.
+    StepLimit,
+    /// Ran for [`Limits::timeout`] without stopping.
+    Timeout,
+    /// Explored as many configurations as [`Machine::explore`] was allowed
+    /// without accepting.
+    BranchLimit,
+    /// An [`Observer`] broke out of the run. Running again resumes it.
+    Interrupted,
+}
+
+impl RunResult {
+    /// The word a test's `expect:` directive uses for this result.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Accepted => "accept",
+            Self::Rejected => "reject",
+            Self::Halted(_) => "halt",
+            Self::Stuck(..) => "stuck",
+            Self::StepLimit => "step-limit",
+            Self::Timeout => "timeout",
+            Self::BranchLimit => "branch-limit",
+            Self::Interrupted => "interrupted",
+        }
+    }
+
+    /// The process exit code the command line reports this result with.
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Self::Accepted | Self::Halted(_) => ExitCode::SUCCESS,
+            Self::Rejected => ExitCode::from(1),
+            Self::Stuck(..) => ExitCode::from(2),
+            Self::StepLimit => ExitCode::from(3),
+            Self::Timeout => ExitCode::from(4),
+            Self::Interrupted => ExitCode::from(5),
+            Self::BranchLimit => ExitCode::from(6),
+        }
+    }
+}
+
+impl Display for RunResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Accepted => write!(f, "accepted"),
+            Self::Rejected => write!(f, "rejected"),
+            Self::Halted(state) => write!(f, "halted in state {state}"),
+            Self::Stuck(state, symbol) => {
+                write!(f, "stuck in state {state} reading {symbol}")
+            }
+            Self::StepLimit => write!(f, "step limit reached"),
+            Self::Timeout => write!(f, "timed out"),
+            Self::BranchLimit => write!(f, "branch limit reached"),
+            Self::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+/// Bounds on a run. The default has none.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// The number of steps after which a run stops.
+    pub max_steps: Option<u64>,
+    /// The wall-clock time after which a run stops.
+    pub timeout: Option<Duration>,
+}
+
+/// One transition taken on the way to an accepting configuration.
+/// [`Machine::describe`] prints it.
+#[derive(Clone)]
+pub struct Branch {
+    parent: Option<usize>,
+    state: StateId,
+    read: Vec<SymbolId>,
+    transition: Transition,
+}
+
+/// What one step of a machine did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    /// The number of steps taken, counting this one.
+    pub steps: u64,
+    /// The source line of the transition that fired.
+    pub line: usize,
+    /// The state the step left.
+    pub old_state: String,
+    /// The state the step entered, which may be the same one.
+    pub new_state: String,
+    /// What the step did on each tape, in order.
+    pub tapes: Vec<TapeEvent>,
+}
+
+/// What one step did on one tape. Head positions count from the first
+/// input cell, so they go negative left of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TapeEvent {
+    /// The symbol under the head before the step.
+    pub read: String,
+    /// The symbol now in the cell that was read. It is `read` again when the
+    /// transition writes `*`.
+    pub written: String,
+    /// How the head moved.
+    pub step: Step,
+    /// The head position before the step.
+    pub old_head: isize,
+    /// The head position after the step.
+    pub new_head: isize,
+}
+
+/// An iterator over the steps of a machine, returned by [`Machine::events`].
+/// It ends when the machine stops, or after yielding the error of a step
+/// that failed.
+pub struct Events<'m> {
+    machine: &'m mut Machine,
+    done: bool,
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<Event, TuringMachineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let event = self.machine.step().transpose();
+        self.done = !matches!(event, Some(Ok(_)));
+        event
+    }
+}
+
+impl FusedIterator for Events<'_> {}
+
+/// The outcome of exploring every branch of a nondeterministic machine.
+pub struct Exploration {
+    /// Accepted if any branch accepts, and otherwise how the search ended.
+    pub result: RunResult,
+    /// The transitions leading to `machine`, when a branch accepted.
+    pub path: Vec<Branch>,
+    /// The accepting machine, if any.
+    pub machine: Option<Machine>,
+    /// The number of configurations expanded.
+    pub explored: usize,
+}
+
+/// A program running over its tapes.
+#[derive(Clone)]
+pub struct Machine {
+    pub(crate) program: Arc<Program>,
+    pub(crate) tapes: Vec<Tape>,
+    pub(crate) state: StateId,
+    pub(crate) steps: u64,
+    // Tape symbols the program never mentions, numbered after its own symbols.
+    extra_symbols: Vec<String>,
+    // How many of the observer callbacks for the current configuration a
+    // run made before it returned, so the next run can skip them.
+    notified: usize,
+}
+
+impl Display for Machine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "STATE: {}", self.program.states.name(self.state))?;
+        for (n, tape) in self.tapes.iter().enumerate() {
+            match self.tapes.len() {
+                1 => writeln!(f, "HEAD: {}", tape.head)?,
+                _ => writeln!(f, "HEAD {}: {}", n + 1, tape.head)?,
+            }
+            let visible = tape.visible();
+            let head = tape.index() - visible.start;
+            let cells = tape
+                .cells
+                .range(visible)
+                .map(|&cell| self.symbol_name(cell));
+            cells.clone().try_for_each(|cell| write!(f, "{cell} "))?;
+            writeln!(f)?;
+            for (i, cell) in cells.enumerate() {
+                if i == head {
+                    write!(f, "^")?;
+                }
+                (0..cell.len()).try_for_each(|_| write!(f, " "))?;
+                if i != head {
+                    write!(f, " ")?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Machine {
+    /// Starts `program` in `initial_state` with the given tape contents.
+    /// Missing tapes start blank.
+    pub fn new(
+        program: Arc<Program>,
+        mode: TapeMode,
+        initial_state: &str,
+        inputs: &[InputTape],
+    ) -> Result<Self, TuringMachineError> {
+        let state = program.states.get(initial_state).ok_or_else(|| {
+            TuringMachineError::Args(format!("{initial_state} is not a state of the program"))
+        })?;
+        if inputs.len() > program.tapes {
+            return Err(TuringMachineError::Args(format!(
+                "{} tapes were given but the program uses {}",
+                inputs.len(),
+                program.tapes
+            )));
+        }
+        let mut extra_symbols = Vec::new();
+        // `_` always stands for the blank in a tape, whatever the program
+        // declares as its blank, so empty cells can be written out. So does
+        // a multi-track cell that is blank on every track.
+        let blank = |symbol: &str| symbol.split('|').all(|track| track == BLANK);
+        let mut intern = |symbol: &str| match program.symbols.get(symbol) {
+            _ if blank(symbol) => Ok(program.blank),
+            Some(id) => Ok(id),
+            None if program.closed_alphabet => Err(TuringMachineError::Tape(format!(
+                "tape symbol {symbol} is not in the declared alphabet"
+            ))),
+            None => {
+                let extra = extra_symbols.iter().position(|s| s == symbol);
+                let extra = extra.unwrap_or_else(|| {
+                    extra_symbols.push(symbol.to_string());
+                    extra_symbols.len() - 1
+                });
+                Ok(program.symbols.len() + extra)
+            }
+        };
+        let mut tapes = Vec::with_capacity(program.tapes);
+        for n in 0..program.tapes {
+            let (cells, head) = match inputs.get(n) {
+                Some(input) => (input.cells.as_slice(), input.head.unwrap_or(0)),
+                None => (Default::default(), 0),
+            };
+            let cells = cells.iter().map(|symbol| intern(symbol));
+            tapes.push(Tape::new(
+                mode,
+                program.blank,
+                [program.left_end, program.right_end],
+                cells.collect::<Result<Vec<_>, _>>()?,
+                head,
+            ));
+        }
+        Ok(Self {
+            tapes,
+            program,
+            state,
+            steps: 0,
+            extra_symbols,
+            notified: 0,
+        })
+    }
+
+    /// The number of steps taken so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The name of the current state.
+    pub fn state(&self) -> &str {
+        self.program.states.name(self.state)
+    }
+
+    /// The number of cells allocated across all tapes.
+    pub fn cells_used(&self) -> usize {
+        self.tapes.iter().map(|tape| tape.cells.len()).sum()
+    }
+
+    /// The symbol under the head of a tape.
+    pub fn read(&self, tape: usize) -> &str {
+        self.symbol_name(self.tapes[tape].read())
+    }
+
+    /// The head position of a tape, counting from the first input cell.
+    pub fn head(&self, tape: usize) -> isize {
+        self.tapes[tape].head
+    }
+
+    /// The symbol at a position of a tape, or the blank for cells the
+    /// machine has not reached.
+    pub fn cell(&self, tape: usize, position: isize) -> &str {
+        self.symbol_name(self.tapes[tape].cell(position))
+    }
+
+    /// The program the machine runs.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Writes the current configuration as one trace line.
+    pub fn write_trace(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(
+            out,
+            "{:>8} {}",
+            self.steps,
+            self.program.states.name(self.state)
+        )?;
+        for (n, tape) in self.tapes.iter().enumerate() {
+            if n > 0 {
+                write!(out, " |")?;
+            }
+            write!(out, " @{}:", tape.head)?;
+            let visible = tape.visible();
+            for (i, &cell) in tape.cells.range(visible.clone()).enumerate() {
+                match self.symbol_name(cell) {
+                    cell if visible.start + i == tape.index() => write!(out, " [{cell}]")?,
+                    cell => write!(out, " {cell}")?,
+                }
+            }
+        }
+        writeln!(out)
+    }
+
+    /// The written cells of each tape, with tapes separated by `|`. The end
+    /// markers of a bounded tape are left out.
+    pub fn contents(&self) -> String {
+        let tapes = self.tapes.iter().map(|tape| {
+            let cells = tape.cells.range(tape.written());
+            let cells = cells.map(|&cell| self.symbol_name(cell));
+            cells.collect::<Vec<_>>().join(" ")
+        });
+        tapes.collect::<Vec<_>>().join(" | ")
+    }
+
+    fn symbol_name(&self, symbol: SymbolId) -> &str {
+        match symbol.checked_sub(self.program.symbols.len()) {
+            Some(extra) => &self.extra_symbols[extra],
+            None => self.program.symbols.name(symbol),
+        }
+    }
+
+    /// Takes one step and describes it. Returns `None` when the machine is
+    /// in a halting state or no transition matches, and an error when the
+    /// step would write or move past what the tape mode allows. The machine
+    /// is left as it was in both cases.
+    pub fn step(&mut self) -> Result<Option<Event>, TuringMachineError> {
+        let Some(transition) = self.transition() else {
+            return Ok(None);
+        };
+        let old_state = self.state;
+        let before = self.tapes.iter().map(|tape| (tape.read(), tape.head));
+        let before = before.collect::<Vec<_>>();
+        self.apply(transition)?;
+        let actions = self.program.actions(&transition);
+        let tapes = (before.into_iter().zip(&self.tapes).zip(actions)).map(
+            |(((read, old_head), tape), &(write, step))| TapeEvent {
+                read: self.symbol_name(read).to_string(),
+                written: self.symbol_name(write.unwrap_or(read)).to_string(),
+                step,
+                old_head,
+                new_head: tape.head,
+            },
+        );
+        Ok(Some(Event {
+            steps: self.steps,
+            line: transition.line,
+            old_state: self.program.states.name(old_state).to_string(),
+            new_state: self.program.states.name(self.state).to_string(),
+            tapes: tapes.collect(),
+        }))
+    }
+
+    /// Steps until the machine stops, yielding an [`Event`] for each step.
+    /// Nothing bounds the run, so limit it with [`Iterator::take`] when it
+    /// may not halt.
+    pub fn events(&mut self) -> Events<'_> {
+        Events {
+            machine: self,
+            done: false,
+        }
+    }
+
+    // The transition the next step takes, if any. Steps that only need to
+    // advance the machine use this with `apply` to skip building an event.
+    fn transition(&self) -> Option<Transition> {
+        if self.program.halting[self.state].is_some() {
+            return None;
+        }
+        let read = |n: usize| self.tapes[n].read();
+        let name = |symbol| self.symbol_name(symbol);
+        self.program.transition(self.state, read, name)
+    }
+
+    fn apply(&mut self, transition: Transition) -> Result<(), TuringMachineError> {
+        self.check(transition)?;
+        self.advance(transition);
+        Ok(())
+    }
+
+    // Whether every tape can take the transition, so that a step either
+    // happens on every tape or on none.
+    fn check(&self, transition: Transition) -> Result<(), TuringMachineError> {
+        let actions = &self.program.actions[transition.action..];
+        for (tape, &(write, step)) in self.tapes.iter().zip(actions) {
+            tape.check(write, step)?;
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self, transition: Transition) {
+        let actions = &self.program.actions[transition.action..];
+        for (tape, &(write, step)) in self.tapes.iter_mut().zip(actions) {
+            if let Some(write) = write {
+                tape.write(write);
+            }
+            tape.step(step);
+        }
+        self.state = transition.next;
+        self.steps += 1;
+        self.notified = 0;
+    }
+
+    /// Explores every branch breadth first, for nondeterministic programs,
+    /// stopping at the first accepting one or after `max_configurations`.
+    pub fn explore(self, limits: Limits, max_configurations: Option<usize>) -> Exploration {
+        let deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+        let mut branches = Vec::<Branch>::new();
+        let mut frontier = VecDeque::from([(self, None)]);
+        let mut explored = 0;
+        let mut limited = false;
+        let path = |branches: &[Branch], mut node: Option<usize>| {
+            let mut path = Vec::new();
+            while let Some(i) = node {
+                path.push(branches[i].clone());
+                node = branches[i].parent;
+            }
+            path.reverse();
+            path
+        };
+        let result = loop {
+            let Some((machine, node)) = frontier.pop_front() else {
+                break if limited {
+                    RunResult::StepLimit
+                } else {
+                    RunResult::Rejected
+                };
+            };
+            match machine.program.halting[machine.state] {
+                Some(Halting::Accept) => {
+                    return Exploration {
+                        result: RunResult::Accepted,
+                        path: path(&branches, node),
+                        machine: Some(machine),
+                        explored,
+                    };
+                }
+                Some(_) => continue,
+                None => {}
+            }
+            if limits.max_steps.is_some_and(|max| machine.steps >= max) {
+                limited = true;
+                continue;
+            }
+            if max_configurations.is_some_and(|max| explored >= max) {
+                break RunResult::BranchLimit;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break RunResult::Timeout;
+            }
+            explored += 1;
+            let state = machine.state;
+            let read = machine.tapes.iter().map(Tape::read).collect::<Vec<_>>();
+            let name = |symbol| machine.symbol_name(symbol);
+            for transition in machine.program.transitions(state, |n| read[n], name) {
+                let mut next = machine.clone();
+                // A branch that falls off a bounded tape just dies.
+                if next.apply(transition).is_ok() {
+                    branches.push(Branch {
+                        parent: node,
+                        state,
+                        read: read.clone(),
+                        transition,
+                    });
+                    frontier.push_back((next, Some(branches.len() - 1)));
+                }
+            }
+        };
+        Exploration {
+            result,
+            path: Vec::new(),
+            machine: None,
+            explored,
+        }
+    }
+
+    /// Describes a branch as the transition line it took.
+    pub fn describe(&self, branch: &Branch) -> String {
+        self.describe_transition(branch.state, &branch.read, branch.transition)
+    }
+
+    /// Describes the transition the next step takes, as [`describe`](Self::describe)
+    /// does, or `None` when the machine has stopped.
+    pub fn next_rule(&self) -> Option<String> {
+        let transition = self.transition()?;
+        let read = self.tapes.iter().map(Tape::read).collect::<Vec<_>>();
+        Some(self.describe_transition(self.state, &read, transition))
+    }
+
+    fn describe_transition(
+        &self,
+        state: StateId,
+        read: &[SymbolId],
+        transition: Transition,
+    ) -> String {
+        let actions = self.program.actions(&transition);
+        let write = (actions.iter().zip(read))
+            .map(|(&(write, _), &read)| self.symbol_name(write.unwrap_or(read)));
+        let read = read.iter().map(|&read| self.symbol_name(read));
+        let step = actions.iter().map(|(_, step)| step.to_string());
+        format!(
+            "line {}: {} {} {} {} {}",
+            transition.line,
+            self.program.states.name(state),
+            read.collect::<Vec<_>>().join(","),
+            write.collect::<Vec<_>>().join(","),
+            step.collect::<Vec<_>>().join(","),
+            self.program.states.name(transition.next)
+        )
+    }
+
+    /// The result of stopping in the current configuration.
+    pub fn outcome(&self) -> RunResult {
+        let state = self.program.states.name(self.state).to_string();
+        match self.program.halting[self.state] {
+            Some(Halting::Accept) => RunResult::Accepted,
+            Some(Halting::Reject) => RunResult::Rejected,
+            Some(Halting::Halt) => RunResult::Halted(state),
+            None => {
+                let read = self.tapes.iter().map(|tape| self.symbol_name(tape.read()));
+                RunResult::Stuck(state, read.collect::<Vec<_>>().join(","))
+            }
+        }
+    }
+
+    /// Runs until the machine stops, a limit is hit or `observer` breaks,
+    /// calling `observer` as it goes.
+    pub fn run(
+        &mut self,
+        limits: Limits,
+        observer: &mut impl Observer,
+    ) -> Result<RunResult, TuringMachineError> {
+        let result = self.run_observed(limits, observer)?;
+        if result != RunResult::Interrupted {
+            observer.on_halt(self, &result)?;
+        }
+        Ok(result)
+    }
+
+    fn run_observed(
+        &mut self,
+        limits: Limits,
+        observer: &mut impl Observer,
+    ) -> Result<RunResult, TuringMachineError> {
+        let deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+        let mut last_check = Instant::now();
+        let mut next_check = self.steps;
+        let mut stride = 1;
+        // A run starting from scratch enters the initial state.
+        let mut entered = self.steps == 0;
+        loop {
+            // Callbacks are numbered in the order they are made for each
+            // configuration, and those an earlier run already made for this
+            // one are skipped, so resuming does not break at once again.
+            let made = std::mem::take(&mut self.notified);
+            if made < 1 && entered && observer.on_state_enter(self)?.is_break() {
+                self.notified = 1;
+                return Ok(RunResult::Interrupted);
+            }
+            if made < 2 && observer.on_step(self)?.is_break() {
+                self.notified = 2;
+                return Ok(RunResult::Interrupted);
+            }
+            if limits.max_steps.is_some_and(|max| self.steps >= max) {
+                self.notified = 2;
+                return Ok(RunResult::StepLimit);
+            }
+            if let Some(deadline) = deadline
+                && self.steps >= next_check
+            {
+                let now = Instant::now();
+                if now >= deadline {
+                    self.notified = 2;
+                    return Ok(RunResult::Timeout);
+                }
+                // Reading the clock costs more than a step, so read it less
+                // often while steps are fast and on every step when they are not.
+                stride = if now - last_check < Duration::from_millis(1) {
+                    (stride * 2).min(1 << 16)
+                } else {
+                    1
+                };
+                last_check = now;
+                next_check = self.steps + stride;
+            }
+            let Some(transition) = self.transition() else {
+                return Ok(self.outcome());
+            };
+            // Observers only hear about writes that are going to happen.
+            self.check(transition)?;
+            for (n, &(write, _)) in self.program.actions(&transition).iter().enumerate() {
+                if let Some(write) = write
+                    && made < 3 + n
+                    && write != self.tapes[n].read()
+                    && observer
+                        .on_write(self, n, self.symbol_name(write))?
+                        .is_break()
+                {
+                    self.notified = 3 + n;
+                    return Ok(RunResult::Interrupted);
+                }
+            }
+            entered = transition.next != self.state;
+            self.advance(transition);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::ControlFlow;
+
+    use super::*;
+    use crate::TurdFile;
+
+    fn machine(source: &str, mode: TapeMode, tapes: &[&[&str]]) -> Machine {
+        let program = Program::compile(&TurdFile::parse("test.turd", source).unwrap());
+        let tapes = tapes.iter().map(|cells| InputTape {
+            cells: cells.iter().map(|cell| cell.to_string()).collect(),
+            head: None,
+        });
+        Machine::new(Arc::new(program), mode, "q", &tapes.collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn exploration_finds_the_accepting_branch() {
+        let source = "accept: yes\nq 1 1 R q\nq 1 1 R r\nr _ _ S yes\n";
+        let exploration =
+            machine(source, TapeMode::Infinite, &[&["1", "1"]]).explore(Limits::default(), None);
+        assert_eq!(exploration.result, RunResult::Accepted);
+        let lines = exploration.path.iter().map(|branch| branch.transition.line);
+        assert_eq!(lines.collect::<Vec<_>>(), [2, 3, 4]);
+        assert_eq!(exploration.machine.unwrap().steps(), 3);
+    }
+
+    #[test]
+    fn exploration_rejects_when_every_branch_dies() {
+        let source = "accept: yes\nq 1 1 R q\nq 1 1 L r\n";
+        let exploration =
+            machine(source, TapeMode::Bounded, &[&["1"]]).explore(Limits::default(), None);
+        assert_eq!(exploration.result, RunResult::Rejected);
+        assert!(exploration.machine.is_none());
+    }
+
+    #[test]
+    fn events_describe_each_step() {
+        let source = "halt: h\nq 1,_ *,1 R,S q\nq _,* 0,* L,R h\n";
+        let mut machine = machine(source, TapeMode::Infinite, &[&["1"]]);
+        let events = machine.events().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].line, events[1].line), (2, 3));
+        assert_eq!(
+            (events[1].old_state.as_str(), events[1].new_state.as_str()),
+            ("q", "h")
+        );
+        let first = &events[0].tapes;
+        assert_eq!(
+            (first[0].read.as_str(), first[0].written.as_str()),
+            ("1", "1")
+        );
+        assert_eq!(
+            (first[1].read.as_str(), first[1].written.as_str()),
+            ("_", "1")
+        );
+        assert_eq!((first[0].old_head, first[0].new_head), (0, 1));
+        assert_eq!(events[1].tapes[1].new_head, 1);
+        assert_eq!(machine.outcome(), RunResult::Halted("h".to_string()));
+    }
+
+    #[test]
+    fn failed_steps_leave_the_machine_unchanged() {
+        let source = "q 1,1 2,2 R,L2 q\n";
+        let mut machine = machine(source, TapeMode::Bounded, &[&["1", "1"], &["1", "1"]]);
+        assert!(machine.step().is_err());
+        assert_eq!(machine.contents(), "1 1 | 1 1");
+        assert_eq!(
+            (machine.head(0), machine.head(1), machine.steps()),
+            (0, 0, 0)
+        );
+    }
+
+    // Breaks on every write and counts the states entered.
+    #[derive(Default)]
+    struct Interrupter {
+        writes: usize,
+        entered: Vec<String>,
+    }
+
+    impl Observer for Interrupter {
+        fn on_write(&mut self, _: &Machine, _: usize, _: &str) -> io::Result<ControlFlow<()>> {
+            self.writes += 1;
+            Ok(ControlFlow::Break(()))
+        }
+
+        fn on_state_enter(&mut self, machine: &Machine) -> io::Result<ControlFlow<()>> {
+            self.entered.push(machine.state().to_string());
+            Ok(ControlFlow::Continue(()))
+        }
+    }
+
+    #[test]
+    fn interrupted_runs_resume() {
+        let mut machine = machine(
+            "halt: h\nq 0 1 R q\nq 1 1 R r\nr _ _ S h\n",
+            TapeMode::Infinite,
+            &[&["0", "1"]],
+        );
+        let mut observer = Interrupter::default();
+        assert_eq!(
+            machine.run(Limits::default(), &mut observer).unwrap(),
+            RunResult::Interrupted
+        );
+        assert_eq!((machine.steps(), observer.writes), (0, 1));
+        let result = machine.run(Limits::default(), &mut observer).unwrap();
+        assert_eq!(result, RunResult::Halted("h".to_string()));
+        assert_eq!(observer.writes, 1);
+        assert_eq!(observer.entered, ["q", "r", "h"]);
+    }
+
+    #[test]
+    fn refused_writes_are_not_observed() {
+        let mut machine = machine("q 1 2 L2 q\n", TapeMode::Bounded, &[&["1"]]);
+        let mut observer = Interrupter::default();
+        assert!(machine.run(Limits::default(), &mut observer).is_err());
+        assert_eq!((machine.steps(), observer.writes), (0, 0));
+    }
+
+    #[test]
+    fn exploration_stops_at_the_branch_limit() {
+        let source = "accept: yes\nq 1 1 R q\nq 1 1 S q\n";
+        let exploration =
+            machine(source, TapeMode::Infinite, &[&["1"]]).explore(Limits::default(), Some(5));
+        assert_eq!(exploration.result, RunResult::BranchLimit);
+        assert_eq!(exploration.explored, 5);
+    }
+}
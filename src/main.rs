@@ -1,177 +1,1005 @@
 use std::{
-    collections::BTreeSet,
-    error::Error,
-    fmt::Display,
-    io::{self, BufRead, Write},
+    io::{self, BufRead, IsTerminal, Write},
+    ops::ControlFlow,
+    path::Path,
+    process::ExitCode,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
     thread,
     time::Duration,
 };
 
-#[derive(Debug)]
-pub enum TuringMachineError {
-    Parse(String),
-    Transformation(String),
-    Args(String),
-    Io(io::Error),
+use turing_machine::{
+    BLANK, Dot, Input, Limits, Machine, Observer, Program, RunResult, Severity, TapeMode, TurdFile,
+    TuringMachineError,
+};
+
+const USAGE: &str = "\
+Usage: turing-machine [command] [options] <input.turd> [input.tape]
+       turing-machine run --batch [options] <input.turd> <tapes>...
+       turing-machine test [options] <input.turd> [tests]...
+       turing-machine debug [options] <input.turd> [input.tape]
+
+Commands:
+  run      Animate the machine until it halts (default)
+  trace    Print one line per step instead of animating
+  check    Report conflicting rules, unreachable states and unused symbols
+  fmt      Print the machine in canonical formatting
+  graph    Print the state diagram in Graphviz DOT format
+  test     Run the test: blocks of the machine and the inputs of the given test
+           files, and compare how they end with their expect:, final:,
+           output: and max-steps: directives
+  debug    Step through the machine interactively, with breakpoints
+
+Options:
+  -i, --initial-state <state>  State to start in, overriding the initial: directive
+  -t, --tape-file <path>       Read the next tape from a file
+      --tape <symbols>         Use the given whitespace-separated symbols as the next tape
+      --input <name>           Run the named input of each tape file
+  -m, --tape-mode <mode>       infinite, semi-infinite-stay, semi-infinite-error,
+                               bounded or circular (default: infinite)
+      --max-steps <n>          Stop after n steps
+      --timeout <duration>     Stop after a wall-clock time such as 500ms, 10s or 2m
+  -n, --nondeterministic       Explore every matching transition breadth-first and
+                               accept if any branch accepts
+      --max-branches <n>       Stop exploring after n configurations
+      --delay-ms <ms>          Delay between animation frames (default: 100)
+      --fps <n>                Animate at n frames per second instead
+      --every <n>              Only print every nth step
+      --headless               Only print the final configuration, without delay
+  -q, --quiet                  Print nothing, only set the exit code
+      --batch                  Run every input of the given tape files, directories
+                               and globs in parallel and print a table of results
+  -h, --help                   Print this help
+
+Matching:
+  Each tape of a transition reads a literal symbol, a class such as [0-9], [^ab]
+  or @name for a set declared with set:, or the * wildcard. When several
+  transitions match, a literal wins over a class, which wins over *. Across
+  tapes, the one with more literals wins, then the one with more classes, and
+  ties go to the one that comes first in the file.";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Run,
+    Trace,
+    Check,
+    Fmt,
+    Graph,
+    Test,
+    Debug,
 }
 
-impl From<io::Error> for TuringMachineError {
-    fn from(v: io::Error) -> Self {
-        Self::Io(v)
-    }
+enum TapeSource {
+    File(String),
+    Inline(String),
 }
 
-impl Error for TuringMachineError {}
+struct Cli {
+    command: Command,
+    turd_filepath: String,
+    tapes: Vec<TapeSource>,
+    input: Option<String>,
+    initial_state: Option<String>,
+    tape_mode: TapeMode,
+    limits: Limits,
+    nondeterministic: bool,
+    max_branches: Option<usize>,
+    delay: Duration,
+    every: u64,
+    headless: bool,
+    quiet: bool,
+    batch: bool,
+}
 
-impl Display for TuringMachineError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Parse(s) | Self::Transformation(s) | Self::Args(s) => s.fmt(f),
-            Self::Io(error) => error.fmt(f),
+impl Cli {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Option<Self>, TuringMachineError> {
+        let mut args = args.peekable();
+        let command = match args.peek().map(String::as_str) {
+            Some("run") => Some(Command::Run),
+            Some("trace") => Some(Command::Trace),
+            Some("check") => Some(Command::Check),
+            Some("fmt") => Some(Command::Fmt),
+            Some("graph") => Some(Command::Graph),
+            Some("test") => Some(Command::Test),
+            Some("debug") => Some(Command::Debug),
+            _ => None,
+        };
+        if command.is_some() {
+            args.next();
         }
-    }
-}
 
-enum Step {
-    Left,
-    Right,
-}
+        let mut positional = Vec::new();
+        let mut tapes = Vec::new();
+        let mut input = None;
+        let mut initial_state = None;
+        let mut tape_mode = TapeMode::Infinite;
+        let mut limits = Limits::default();
+        let mut nondeterministic = false;
+        let mut max_branches = None;
+        let mut delay = Duration::from_millis(100);
+        let mut every = 1;
+        let mut headless = false;
+        let mut quiet = false;
+        let mut batch = false;
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline.clone().or_else(|| args.next()).ok_or_else(|| {
+                    TuringMachineError::Args(format!("{flag} expects a value\n\n{USAGE}"))
+                })
+            };
+            let number = |value: String| {
+                value.parse().map_err(|_| {
+                    TuringMachineError::Args(format!("{flag} expects a number, got {value}"))
+                })
+            };
+            match flag {
+                "-h" | "--help" => return Ok(None),
+                "-i" | "--initial-state" => initial_state = Some(value()?),
+                "-t" | "--tape-file" => tapes.push(TapeSource::File(value()?)),
+                "--tape" => tapes.push(TapeSource::Inline(value()?)),
+                "--input" => input = Some(value()?),
+                "-m" | "--tape-mode" => tape_mode = TapeMode::try_from(value()?.as_str())?,
+                "--max-steps" => limits.max_steps = Some(number(value()?)?),
+                "--timeout" => limits.timeout = Some(parse_duration(&value()?)?),
+                "-n" | "--nondeterministic" => nondeterministic = true,
+                "--max-branches" => max_branches = Some(number(value()?)? as usize),
+                "--delay-ms" => delay = Duration::from_millis(number(value()?)?),
+                "--fps" => match number(value()?)? {
+                    0 => return Err(TuringMachineError::Args("--fps must be positive".into())),
+                    fps => delay = Duration::from_nanos(1_000_000_000 / fps),
+                },
+                "--every" => match number(value()?)? {
+                    0 => return Err(TuringMachineError::Args("--every must be positive".into())),
+                    n => every = n,
+                },
+                "--headless" => headless = true,
+                "-q" | "--quiet" => quiet = true,
+                "--batch" => batch = true,
+                _ if flag.starts_with('-') && flag != "-" => {
+                    return Err(TuringMachineError::Args(format!(
+                        "Unknown option {flag}\n\n{USAGE}"
+                    )));
+                }
+                _ => positional.push(arg),
+            }
+        }
 
-impl TryFrom<&str> for Step {
-    type Error = TuringMachineError;
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value {
-            "L" => Ok(Self::Left),
-            "R" => Ok(Self::Right),
-            _ => Err(TuringMachineError::Transformation(format!(
-                "{value} is not a valid step. Expected 'L' or 'R'"
-            ))),
+        if command == Some(Command::Debug) && (batch || nondeterministic) {
+            return Err(TuringMachineError::Args(
+                "debug steps through a single deterministic run, so it cannot be used with --batch or --nondeterministic".to_string(),
+            ));
         }
-    }
-}
 
-type State<'a> = &'a str;
-type Symbol<'a> = &'a str;
+        let mut positional = positional.into_iter();
+        let Some(turd_filepath) = positional.next() else {
+            return Err(TuringMachineError::Args(format!(
+                "Input file is not provided\n\n{USAGE}"
+            )));
+        };
+        let paths = positional.collect::<Vec<_>>();
+        if let [_, extra, ..] = &paths[..]
+            && !batch
+            && command != Some(Command::Test)
+        {
+            return Err(TuringMachineError::Args(format!(
+                "Unexpected argument {extra}\n\n{USAGE}"
+            )));
+        }
+        tapes.splice(0..0, paths.into_iter().map(TapeSource::File));
 
-struct Turd<'a> {
-    current: State<'a>,
-    read: Symbol<'a>,
-    write: Symbol<'a>,
-    step: Step,
-    next: State<'a>,
+        Ok(Some(Self {
+            command: command.unwrap_or(Command::Run),
+            turd_filepath,
+            tapes,
+            input,
+            initial_state,
+            tape_mode,
+            limits,
+            nondeterministic,
+            max_branches,
+            delay,
+            every,
+            headless,
+            quiet,
+            batch,
+        }))
+    }
 }
 
-impl<'a> Turd<'a> {
-    fn parse_turd(filepath: &str, s: (usize, &'a str)) -> Result<Self, TuringMachineError> {
-        let mut tokens = s.1.split_whitespace();
-        if tokens.clone().count() != 5 {
-            return Err(TuringMachineError::Parse(format!(
-                "{filepath}:{}: A single turd is expected to have 5 tokens",
-                s.0 + 1
+fn parse_duration(value: &str) -> Result<Duration, TuringMachineError> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let seconds = match (number.parse::<f64>(), unit) {
+        (Ok(n), "ms") => n / 1000.0,
+        (Ok(n), "s") => n,
+        (Ok(n), "m") => n * 60.0,
+        _ => {
+            return Err(TuringMachineError::Args(format!(
+                "{value} is not a valid duration. Expected a number followed by 'ms', 's' or 'm'"
             )));
         }
+    };
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| TuringMachineError::Args(format!("{value} is too long a duration")))
+}
 
-        Ok(Self {
-            current: tokens.next().unwrap(),
-            read: tokens.next().unwrap(),
-            write: tokens.next().unwrap(),
-            step: tokens.next().unwrap().try_into()?,
-            next: tokens.next().unwrap(),
-        })
+// A directory stands for the files in it, and a path whose file name has
+// `*` or `?` for the files it matches, both in name order.
+fn expand_path(path: &str) -> Result<Vec<String>, TuringMachineError> {
+    let (dir, pattern) = match Path::new(path) {
+        dir if dir.is_dir() => (dir, "*"),
+        file => match file.file_name().and_then(|name| name.to_str()) {
+            Some(name) if name.contains(['*', '?']) => (file.parent().unwrap(), name),
+            _ => return Ok(vec![path.to_string()]),
+        },
+    };
+    let mut paths = Vec::new();
+    let entries = std::fs::read_dir(if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    })?;
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if !name.starts_with('.') && glob_match(pattern, name) && entry.file_type()?.is_file() {
+            paths.push(dir.join(name).display().to_string());
+        }
+    }
+    if paths.is_empty() {
+        return Err(TuringMachineError::Args(format!(
+            "{path} matches no tape files"
+        )));
     }
+    paths.sort();
+    Ok(paths)
+}
 
-    fn states_of_turds(turds: &[Self]) -> impl Iterator<Item = State<'_>> {
-        turds
-            .iter()
-            .flat_map(|t| [t.current, t.next])
-            .collect::<BTreeSet<_>>()
-            .into_iter()
+fn glob_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern {
+            [] => name.is_empty(),
+            ['*', rest @ ..] => (0..=name.len()).any(|i| matches(rest, &name[i..])),
+            ['?', rest @ ..] => !name.is_empty() && matches(rest, &name[1..]),
+            [c, rest @ ..] => name.first() == Some(c) && matches(rest, &name[1..]),
+        }
     }
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    matches(&pattern, &name.chars().collect::<Vec<_>>())
 }
 
-struct Machine<'a> {
-    tape: Vec<Symbol<'a>>,
-    head: usize,
-    state: State<'a>,
+// Runs one input to the end without printing anything.
+fn run_quietly(
+    cli: &Cli,
+    program: &Arc<Program>,
+    input: &Input,
+    limits: Limits,
+) -> Result<(RunResult, Option<Machine>), TuringMachineError> {
+    let state = (cli.initial_state.as_deref().or(input.state.as_deref()))
+        .or(program.initial_state())
+        .ok_or_else(|| {
+            TuringMachineError::Args(
+                "No initial state given. Pass --initial-state <state> or declare initial: <state>"
+                    .to_string(),
+            )
+        })?;
+    let mut machine = Machine::new(program.clone(), cli.tape_mode, state, &input.tapes)?;
+    if cli.nondeterministic {
+        let exploration = machine.explore(limits, cli.max_branches);
+        return Ok((exploration.result, exploration.machine));
+    }
+    let result = machine.run(limits, &mut ())?;
+    Ok((result, Some(machine)))
 }
 
-impl Display for Machine<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "STATE: {}", self.state)?;
-        self.tape.iter().try_for_each(|cell| write!(f, "{cell} "))?;
-        writeln!(f)?;
-        for (i, cell) in self.tape.iter().enumerate() {
-            if i == self.head {
-                write!(f, "^")?;
-            }
-            (0..cell.len()).try_for_each(|_| write!(f, " "))?;
-            if i != self.head {
-                write!(f, " ")?;
-            }
-        }
-        writeln!(f)
+// Picks the input to run from a tape file, by name when one is given.
+fn select_input(
+    filepath: &str,
+    mut inputs: Vec<Input>,
+    name: Option<&str>,
+) -> Result<Input, TuringMachineError> {
+    if inputs.len() == 1 && name.is_none() {
+        return Ok(inputs.remove(0));
+    }
+    let names = inputs
+        .iter()
+        .map(|input| input.name.as_deref().unwrap_or("?"));
+    let names = names.collect::<Vec<_>>().join(", ");
+    let Some(name) = name else {
+        return Err(TuringMachineError::Args(format!(
+            "{filepath} has several inputs ({names}). Choose one with --input <name>"
+        )));
+    };
+    match inputs
+        .iter()
+        .position(|input| input.name.as_deref() == Some(name))
+    {
+        Some(i) => Ok(inputs.remove(i)),
+        None if inputs.iter().all(|input| input.name.is_none()) => Err(TuringMachineError::Args(
+            format!("{filepath} has no named inputs to choose {name} from"),
+        )),
+        None => Err(TuringMachineError::Args(format!(
+            "{filepath} has no input named {name}. Expected one of {names}"
+        ))),
     }
 }
 
-impl<'a> Machine<'a> {
-    fn next(&mut self, program: &'a [Turd]) -> bool {
-        for turd in program {
-            if turd.current == self.state && turd.read == self.tape[self.head] {
-                self.tape[self.head] = turd.write;
-                self.head = match turd.step {
-                    Step::Left if self.head == 0 => self.tape.len() - 1,
-                    Step::Left => self.head - 1,
-                    Step::Right => (self.head + 1) % self.tape.len(),
+// Every input of the given tape sources, named after the file they come
+// from and their input: name.
+fn load_inputs(sources: &[TapeSource]) -> Result<Vec<(String, Input)>, TuringMachineError> {
+    let mut inputs = Vec::new();
+    for source in sources {
+        let files = match source {
+            TapeSource::File(path) => (expand_path(path)?.into_iter())
+                .map(|path| Ok((std::fs::read_to_string(&path)?, path)))
+                .collect::<Result<Vec<_>, TuringMachineError>>()?,
+            TapeSource::Inline(tape) => vec![(tape.clone(), "--tape".to_string())],
+        };
+        for (content, path) in files {
+            for input in Input::parse_file(&path, &content)? {
+                let name = match &input.name {
+                    Some(name) => format!("{path}:{name}"),
+                    None => path.clone(),
                 };
-                self.state = turd.next;
-                return true;
+                inputs.push((name, input));
             }
         }
-        false
     }
+    Ok(inputs)
+}
+
+// Maps `f` over `items` on as many threads as there are cores, keeping the
+// results in the order of the items.
+fn in_parallel<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut results = thread::scope(|scope| {
+        let workers = (0..workers.min(items.len())).map(|_| {
+            scope.spawn(|| {
+                let mut results = Vec::new();
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    results.push((i, f(item)));
+                }
+                results
+            })
+        });
+        let workers = workers.collect::<Vec<_>>();
+        (workers.into_iter())
+            .flat_map(|worker| worker.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|&(i, _)| i);
+    results.into_iter().map(|(_, result)| result).collect()
 }
 
-pub fn main() -> Result<(), TuringMachineError> {
-    let mut args = std::env::args();
-    let program = args.next().unwrap();
-    if args.len() < 2 {
-        eprintln!("Error: input file is not provided");
+fn run_batch(cli: &Cli, program: &Arc<Program>) -> Result<ExitCode, TuringMachineError> {
+    let inputs = load_inputs(&cli.tapes)?;
+    if inputs.is_empty() {
         return Err(TuringMachineError::Args(format!(
-            "Usage: {program} <input.turd> <input.tape>"
+            "--batch expects tape files, directories or globs to run\n\n{USAGE}"
         )));
     }
-    let turd_filepath = &args.next().unwrap();
-    let tape_filepath = &args.next().unwrap();
+    let outcomes = in_parallel(&inputs, |(_, input)| {
+        run_quietly(cli, program, input, cli.limits)
+    });
+
+    let mut passed = 0;
+    let mut rows = vec![["INPUT", "RESULT", "STEPS", "CELLS", "TAPE"].map(String::from)];
+    for ((name, _), outcome) in inputs.iter().zip(outcomes) {
+        let row = match outcome {
+            Ok((result, machine)) => {
+                passed += usize::from(matches!(result, RunResult::Accepted | RunResult::Halted(_)));
+                let (steps, cells, tape) = match machine {
+                    Some(machine) => (
+                        machine.steps().to_string(),
+                        machine.cells_used().to_string(),
+                        machine.contents(),
+                    ),
+                    None => ("-".to_string(), "-".to_string(), String::new()),
+                };
+                [name.clone(), result.to_string(), steps, cells, tape]
+            }
+            Err(error) => [
+                name.clone(),
+                format!("error: {error}"),
+                "-".to_string(),
+                "-".to_string(),
+                String::new(),
+            ],
+        };
+        rows.push(row);
+    }
+    if !cli.quiet {
+        let width = |n: usize| rows.iter().map(|row| row[n].chars().count()).max().unwrap();
+        let widths = [width(0), width(1), width(2), width(3)];
+        let mut stdout = io::BufWriter::new(io::stdout().lock());
+        for [name, result, steps, cells, tape] in &rows {
+            let line = format!(
+                "{name:w0$}  {result:w1$}  {steps:>w2$}  {cells:>w3$}  {tape}",
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+            );
+            writeln!(stdout, "{}", line.trim_end())?;
+        }
+        writeln!(
+            stdout,
+            "{passed} of {} inputs accepted or halted",
+            inputs.len()
+        )?;
+        stdout.flush()?;
+    }
+    Ok(ExitCode::from(u8::from(passed < inputs.len())))
+}
+
+// Lines up the expected and actual tapes cell by cell, with carets under
+// the cells that differ.
+fn tape_diff(expected: &[&str], actual: &[&str]) -> String {
+    let (mut left, mut right, mut carets) = (String::new(), String::new(), String::new());
+    for i in 0..expected.len().max(actual.len()) {
+        let (e, a) = (expected.get(i).unwrap_or(&""), actual.get(i).unwrap_or(&""));
+        let width = e.chars().count().max(a.chars().count());
+        left.push_str(&format!("{e:width$} "));
+        right.push_str(&format!("{a:width$} "));
+        let caret = if e == a { " " } else { "^" };
+        carets.push_str(&format!("{} ", caret.repeat(width)));
+    }
+    format!(
+        "  expected: {}\n  actual:   {}\n            {}",
+        left.trim_end(),
+        right.trim_end(),
+        carets.trim_end()
+    )
+}
 
-    let content = std::fs::read_to_string(turd_filepath)?;
-    let turds = content
-        .lines()
-        .map(str::trim)
-        .enumerate()
-        .filter_map(|x| (!x.1.is_empty()).then(|| Turd::parse_turd(&turd_filepath, x)))
-        .collect::<Result<Vec<_>, _>>()?;
+// Runs a test input and describes each way it did not end as expected.
+fn failures(cli: &Cli, program: &Arc<Program>, input: &Input) -> Vec<String> {
+    let expected = &input.expected;
+    let limits = Limits {
+        max_steps: match (cli.limits.max_steps, expected.max_steps) {
+            (Some(cli), Some(test)) => Some(cli.min(test)),
+            (cli, test) => cli.or(test),
+        },
+        ..cli.limits
+    };
+    let (result, machine) = match run_quietly(cli, program, input, limits) {
+        Ok(outcome) => outcome,
+        Err(error) => return vec![format!("Error: {error}")],
+    };
+    let mut failures = Vec::new();
+    let passed = |kind| match &expected.result {
+        Some(expected) => expected == kind,
+        None => kind == "accept" || kind == "halt",
+    };
+    if !passed(result.kind()) {
+        let expected = expected.result.as_deref().unwrap_or("accept or halt");
+        failures.push(format!("Expected {expected}, got {result}"));
+    }
+    let state = machine.as_ref().map(|m| m.state());
+    if let Some(expected) = &expected.state
+        && state != Some(expected.as_str())
+    {
+        let state = state.unwrap_or("no final state");
+        failures.push(format!(
+            "Expected to end in state {expected}, ended in {state}"
+        ));
+    }
+    if let Some(output) = &expected.output {
+        // `_` stands for the blank, as it does in tapes.
+        let blank = program.blank();
+        let output = output.iter().map(|cell| match cell.as_str() {
+            BLANK => blank,
+            cell => cell,
+        });
+        let output = output.collect::<Vec<_>>();
+        let contents = machine.as_ref().map(Machine::contents).unwrap_or_default();
+        let contents = contents.split_whitespace().collect::<Vec<_>>();
+        if output != contents {
+            let diff = tape_diff(&output, &contents);
+            failures.push(format!("Final tape differs:\n{diff}"));
+        }
+    }
+    failures
+}
 
-    let states = Turd::states_of_turds(&turds);
+fn run_tests(
+    cli: &Cli,
+    program: &Arc<Program>,
+    file: &TurdFile,
+) -> Result<ExitCode, TuringMachineError> {
+    let inline = file.tests().iter().map(|test| {
+        let name = test.name.as_deref().unwrap_or_default();
+        (format!("{}:{name}", cli.turd_filepath), test)
+    });
+    let loaded = load_inputs(&cli.tapes)?;
+    let loaded = loaded.iter().map(|(name, test)| (name.clone(), test));
+    let tests = inline.chain(loaded).collect::<Vec<_>>();
+    if tests.is_empty() {
+        return Err(TuringMachineError::Args(format!(
+            "No tests found. Add test: blocks to {} or pass test files\n\n{USAGE}",
+            cli.turd_filepath
+        )));
+    }
+    let failures = in_parallel(&tests, |(_, input)| failures(cli, program, input));
+    let failed = failures.iter().filter(|f| !f.is_empty()).count();
+    if !cli.quiet {
+        let mut stdout = io::BufWriter::new(io::stdout().lock());
+        match tests.len() {
+            1 => writeln!(stdout, "running 1 test")?,
+            n => writeln!(stdout, "running {n} tests")?,
+        }
+        for ((name, _), failures) in tests.iter().zip(&failures) {
+            let status = if failures.is_empty() { "ok" } else { "FAILED" };
+            writeln!(stdout, "test {name} ... {status}")?;
+        }
+        if failed > 0 {
+            writeln!(stdout, "\nfailures:")?;
+            for ((name, _), failures) in tests.iter().zip(&failures) {
+                if !failures.is_empty() {
+                    writeln!(stdout, "\n---- {name} ----")?;
+                    failures.iter().try_for_each(|f| writeln!(stdout, "{f}"))?;
+                }
+            }
+        }
+        writeln!(
+            stdout,
+            "\ntest result: {}. {} passed; {failed} failed",
+            if failed == 0 { "ok" } else { "FAILED" },
+            tests.len() - failed
+        )?;
+        stdout.flush()?;
+    }
+    Ok(ExitCode::from(u8::from(failed > 0)))
+}
 
+fn prompt_initial_state(file: &TurdFile) -> Result<String, TuringMachineError> {
+    if !io::stdin().is_terminal() {
+        return Err(TuringMachineError::Args(
+            "No initial state given. Pass --initial-state <state> or declare initial: <state>"
+                .to_string(),
+        ));
+    }
     println!("Possible states:");
-    states.for_each(|state| println!("{state}"));
+    file.states().for_each(|state| println!("{state}"));
     print!("Initial_state: ");
     io::stdout().flush()?;
-    let initial_state = io::stdin().lock().lines().next().unwrap()?;
+    let initial_state = io::stdin().lock().lines().next().transpose()?;
     println!();
+    initial_state.ok_or_else(|| TuringMachineError::Args("No initial state given".to_string()))
+}
+
+// Prints the configurations of a run as the options ask, then its result.
+struct Printer<'c, W: Write> {
+    cli: &'c Cli,
+    out: W,
+    // The step of the last configuration printed.
+    shown: Option<u64>,
+}
+
+impl<W: Write> Printer<'_, W> {
+    fn show(&mut self, machine: &Machine) -> io::Result<()> {
+        match self.cli.command {
+            Command::Trace => machine.write_trace(&mut self.out)?,
+            _ => write!(self.out, "{machine}")?,
+        }
+        self.shown = Some(machine.steps());
+        Ok(())
+    }
+}
 
-    let binding = std::fs::read_to_string(tape_filepath)?;
-    let mut machine = Machine {
-        tape: binding.split_whitespace().collect(),
-        head: 0,
-        state: &initial_state,
+impl<W: Write> Observer for Printer<'_, W> {
+    fn on_step(&mut self, machine: &Machine) -> io::Result<ControlFlow<()>> {
+        let cli = self.cli;
+        if cli.quiet || cli.headless || !machine.steps().is_multiple_of(cli.every) {
+            return Ok(ControlFlow::Continue(()));
+        }
+        self.show(machine)?;
+        if cli.command == Command::Run && !cli.delay.is_zero() {
+            self.out.flush()?;
+            thread::sleep(cli.delay);
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn on_halt(&mut self, machine: &Machine, result: &RunResult) -> io::Result<()> {
+        if self.cli.quiet {
+            return Ok(());
+        }
+        let limited = matches!(result, RunResult::StepLimit | RunResult::Timeout);
+        // The last configuration may already be on screen from on_step.
+        if self.shown != Some(machine.steps()) {
+            if limited {
+                writeln!(self.out, "Did not halt. Last configuration:")?;
+            }
+            self.show(machine)?;
+        } else if limited {
+            writeln!(self.out, "Did not halt.")?;
+        }
+        writeln!(self.out, "RESULT: {result} after {} steps", machine.steps())
+    }
+}
+
+const DEBUG_HELP: &str = "\
+Commands:
+  step [n]                 Take one step, or n steps, stopping at breakpoints (s)
+  continue                 Run until a breakpoint, a limit or the machine stops (c)
+  break                    List the breakpoints (b)
+  break <state> [symbols]  Stop in a state, or only when it reads the given
+                           comma-separated symbols, one per tape (* reads any)
+  break step <n>           Stop once n steps have been taken
+  delete [n]               Delete breakpoint n, or every breakpoint (d)
+  print                    Print the configuration (p)
+  tape [n]                 Print the n cells either side of each head (default 10) (t)
+  rule                     Print the transition the next step takes (r)
+  help                     Print this help (h)
+  quit                     Stop debugging (q)
+An empty line repeats the last command.";
+
+// The widest window `tape` prints, in cells either side of the head.
+const MAX_TAPE_WINDOW: u64 = 1000;
+
+// A configuration `debug` stops at.
+enum Breakpoint {
+    State(String),
+    Read(String, Vec<String>),
+    Step(u64),
+}
+
+impl Breakpoint {
+    fn parse(args: &[&str]) -> Result<Self, TuringMachineError> {
+        match args {
+            ["step", n] => match n.parse() {
+                Ok(n) => Ok(Self::Step(n)),
+                Err(_) => Err(TuringMachineError::Args(format!("{n} is not a step count"))),
+            },
+            [state] => Ok(Self::State(state.to_string())),
+            [state, read] => Ok(Self::Read(
+                state.to_string(),
+                read.split(',').map(str::to_string).collect(),
+            )),
+            _ => Err(TuringMachineError::Args(
+                "Expected break <state> [symbols] or break step <n>".to_string(),
+            )),
+        }
+    }
+
+    fn hit(&self, machine: &Machine) -> bool {
+        match self {
+            Self::State(state) => machine.state() == state,
+            Self::Read(state, read) => {
+                machine.state() == state
+                    && (read.iter().enumerate())
+                        .all(|(n, symbol)| symbol == "*" || machine.read(n) == symbol)
+            }
+            Self::Step(steps) => machine.steps() == *steps,
+        }
+    }
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::State(state) => write!(f, "state {state}"),
+            Self::Read(state, read) => write!(f, "state {state} reading {}", read.join(",")),
+            Self::Step(steps) => write!(f, "step {steps}"),
+        }
+    }
+}
+
+// Interrupts a run at the first breakpoint it reaches.
+struct Breaker<'b> {
+    breakpoints: &'b [Breakpoint],
+    hit: Option<usize>,
+}
+
+impl Observer for Breaker<'_> {
+    fn on_step(&mut self, machine: &Machine) -> io::Result<ControlFlow<()>> {
+        self.hit = self.breakpoints.iter().position(|b| b.hit(machine));
+        Ok(match self.hit {
+            Some(_) => ControlFlow::Break(()),
+            None => ControlFlow::Continue(()),
+        })
+    }
+}
+
+// Runs `machine` until `limits` or a breakpoint and says where it stopped.
+// Reaching `target` steps is where `step` means to stop, so it goes unsaid.
+fn resume(
+    machine: &mut Machine,
+    breakpoints: &[Breakpoint],
+    limits: Limits,
+    target: Option<u64>,
+) -> Result<(), TuringMachineError> {
+    let mut breaker = Breaker {
+        breakpoints,
+        hit: None,
+    };
+    let result = machine.run(limits, &mut breaker)?;
+    print!("{machine}");
+    match (result, breaker.hit) {
+        (RunResult::Interrupted, Some(n)) => println!("Breakpoint {}: {}", n + 1, breakpoints[n]),
+        // A step that lands on a stopped machine still says how it ended.
+        (RunResult::StepLimit, _) if target == Some(machine.steps()) => {
+            if machine.next_rule().is_none() {
+                let steps = machine.steps();
+                println!("RESULT: {} after {steps} steps", machine.outcome());
+            }
+        }
+        (result, _) => println!("RESULT: {result} after {} steps", machine.steps()),
+    }
+    Ok(())
+}
+
+// Runs one command of a debug session. Returns false to end the session.
+fn debug_command(
+    cli: &Cli,
+    machine: &mut Machine,
+    breakpoints: &mut Vec<Breakpoint>,
+    command: &str,
+    args: &[&str],
+) -> Result<bool, TuringMachineError> {
+    let number = |default: u64| match args {
+        [] => Ok(default),
+        [n] => n
+            .parse()
+            .map_err(|_| TuringMachineError::Args(format!("{n} is not a number"))),
+        _ => Err(TuringMachineError::Args(format!(
+            "{command} takes at most one number"
+        ))),
     };
+    match command {
+        "s" | "step" => {
+            let target = machine.steps() + number(1)?;
+            let max_steps = cli.limits.max_steps.map_or(target, |max| max.min(target));
+            let limits = Limits {
+                max_steps: Some(max_steps),
+                ..cli.limits
+            };
+            resume(machine, breakpoints, limits, Some(target))?;
+        }
+        "c" | "continue" => resume(machine, breakpoints, cli.limits, None)?,
+        "b" | "break" if args.is_empty() => {
+            if breakpoints.is_empty() {
+                println!("No breakpoints");
+            }
+            for (n, breakpoint) in breakpoints.iter().enumerate() {
+                println!("{}: {breakpoint}", n + 1);
+            }
+        }
+        "b" | "break" => {
+            let breakpoint = Breakpoint::parse(args)?;
+            if let Breakpoint::State(state) | Breakpoint::Read(state, _) = &breakpoint
+                && !machine.program().has_state(state)
+            {
+                return Err(TuringMachineError::Args(format!(
+                    "{state} is not a state of the program"
+                )));
+            }
+            let tapes = machine.program().tapes();
+            if let Breakpoint::Read(_, read) = &breakpoint
+                && read.len() != tapes
+            {
+                return Err(TuringMachineError::Args(format!(
+                    "Expected {tapes} comma-separated symbol(s), one per tape, found {}",
+                    read.len()
+                )));
+            }
+            println!("Breakpoint {}: {breakpoint}", breakpoints.len() + 1);
+            breakpoints.push(breakpoint);
+        }
+        "d" | "delete" if args.is_empty() => breakpoints.clear(),
+        "d" | "delete" => match usize::try_from(number(0)?) {
+            Ok(n) if (1..=breakpoints.len()).contains(&n) => {
+                breakpoints.remove(n - 1);
+            }
+            _ => {
+                return Err(TuringMachineError::Args(format!(
+                    "There is no breakpoint {}",
+                    args[0]
+                )));
+            }
+        },
+        "p" | "print" => print!("{machine}"),
+        "t" | "tape" => {
+            let radius = match number(10)? {
+                radius @ 0..=MAX_TAPE_WINDOW => radius as isize,
+                _ => {
+                    return Err(TuringMachineError::Args(format!(
+                        "tape prints at most {MAX_TAPE_WINDOW} cells either side of the head"
+                    )));
+                }
+            };
+            let tapes = machine.program().tapes();
+            for tape in 0..tapes {
+                let head = machine.head(tape);
+                let cells = (head - radius..=head + radius).map(|position| {
+                    match machine.cell(tape, position) {
+                        cell if position == head => format!("[{cell}]"),
+                        cell => cell.to_string(),
+                    }
+                });
+                let cells = cells.collect::<Vec<_>>().join(" ");
+                match tapes {
+                    1 => println!("{}..{}: {cells}", head - radius, head + radius),
+                    _ => println!(
+                        "TAPE {} {}..{}: {cells}",
+                        tape + 1,
+                        head - radius,
+                        head + radius
+                    ),
+                }
+            }
+        }
+        "r" | "rule" => match machine.next_rule() {
+            Some(rule) => println!("{rule}"),
+            None => println!("No transition applies: {}", machine.outcome()),
+        },
+        "h" | "help" => println!("{DEBUG_HELP}"),
+        "q" | "quit" => return Ok(false),
+        _ => {
+            return Err(TuringMachineError::Args(format!(
+                "Unknown command {command}. Type help for a list of commands"
+            )));
+        }
+    }
+    Ok(true)
+}
+
+// Reads commands from stdin until quit or the end of input.
+fn debug(cli: &Cli, mut machine: Machine) -> Result<ExitCode, TuringMachineError> {
+    let mut breakpoints = Vec::new();
+    let mut last = String::new();
+    let mut lines = io::stdin().lock().lines();
+    print!("{machine}");
+    println!("Type help for a list of commands.");
     loop {
-        print!("{machine}");
-        thread::sleep(Duration::from_millis(100));
-        if !machine.next(&turds) {
+        print!("(debug) ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            println!();
             break;
+        };
+        // An empty line repeats the last command, as in gdb.
+        if !line.trim().is_empty() {
+            last = line;
+        }
+        let mut words = last.split_whitespace();
+        let Some(command) = words.next() else {
+            continue;
+        };
+        let args = words.collect::<Vec<_>>();
+        match debug_command(cli, &mut machine, &mut breakpoints, command, &args) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(error) => println!("Error: {error}"),
         }
     }
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+fn try_main() -> Result<ExitCode, TuringMachineError> {
+    let Some(cli) = Cli::parse(std::env::args().skip(1))? else {
+        println!("{USAGE}");
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    let content = std::fs::read_to_string(&cli.turd_filepath)?;
+    let file = TurdFile::parse(&cli.turd_filepath, &content)?;
+    let program = Arc::new(Program::compile(&file));
+    match cli.command {
+        Command::Run | Command::Trace | Command::Debug => {}
+        Command::Check => {
+            let diagnostics = file.check(&cli.turd_filepath, cli.initial_state.as_deref());
+            diagnostics.iter().for_each(|d| println!("{d}"));
+            let warnings = diagnostics
+                .iter()
+                .filter(|d| d.severity() == Severity::Warning)
+                .count();
+            let summary = match warnings {
+                0 => "OK".to_string(),
+                1 => "1 warning".to_string(),
+                n => format!("{n} warnings"),
+            };
+            println!(
+                "{}: {summary} ({} states, {} transitions)",
+                cli.turd_filepath,
+                program.states(),
+                file.turds().len()
+            );
+            return Ok(ExitCode::from(u8::from(warnings > 0)));
+        }
+        Command::Fmt => {
+            print!("{file}");
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Graph => {
+            print!("{}", Dot(&file));
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Test => return run_tests(&cli, &program, &file),
+    }
+
+    if cli.batch {
+        return run_batch(&cli, &program);
+    }
+    let mut input = Input::default();
+    for source in &cli.tapes {
+        let (path, content) = match source {
+            TapeSource::File(path) => (path.as_str(), std::fs::read_to_string(path)?),
+            TapeSource::Inline(tape) => ("--tape", tape.clone()),
+        };
+        let inputs = Input::parse_file(path, &content)?;
+        // --input names inputs of tape files, not of tapes given inline.
+        let name = cli.input.as_deref().filter(|_| path != "--tape");
+        let selected = select_input(path, inputs, name)?;
+        input.state = input.state.or(selected.state);
+        input.tapes.extend(selected.tapes);
+    }
+    let initial_state = match (
+        cli.initial_state.as_deref(),
+        input.state,
+        program.initial_state(),
+    ) {
+        (Some(state), _, _) => state.to_string(),
+        (None, Some(state), _) => state,
+        (None, None, Some(state)) => state.to_string(),
+        (None, None, None) => prompt_initial_state(&file)?,
+    };
+    let mut machine = Machine::new(program, cli.tape_mode, initial_state.trim(), &input.tapes)?;
+    if cli.command == Command::Debug {
+        return debug(&cli, machine);
+    }
+
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    if cli.nondeterministic {
+        let exploration = machine.explore(cli.limits, cli.max_branches);
+        if !cli.quiet {
+            writeln!(stdout, "Explored {} configurations", exploration.explored)?;
+            if let Some(machine) = &exploration.machine {
+                writeln!(stdout, "Accepting path:")?;
+                for branch in &exploration.path {
+                    writeln!(stdout, "  {}", machine.describe(branch))?;
+                }
+                write!(stdout, "{machine}")?;
+            }
+            writeln!(stdout, "RESULT: {}", exploration.result)?;
+        }
+        stdout.flush()?;
+        return Ok(exploration.result.exit_code());
+    }
+    let mut printer = Printer {
+        cli: &cli,
+        out: stdout,
+        shown: None,
+    };
+    let result = machine.run(cli.limits, &mut printer)?;
+    printer.out.flush()?;
+    Ok(result.exit_code())
+}
+
+pub fn main() -> ExitCode {
+    try_main().unwrap_or_else(|error| {
+        match error {
+            TuringMachineError::Parse(_) => eprintln!("{error}"),
+            _ => eprintln!("Error: {error}"),
+        }
+        error.exit_code()
+    })
 }
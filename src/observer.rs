@@ -0,0 +1,44 @@
+use std::{io, ops::ControlFlow};
+
+use crate::machine::{Machine, RunResult};
+
+/// Callbacks from [`Machine::run`]. Each does nothing by default. The ones
+/// returning [`ControlFlow`] can break to interrupt the run, which then
+/// returns [`RunResult::Interrupted`] and can be resumed with another call
+/// to `run`. A resumed run, or one that follows a run stopped by a limit,
+/// does not repeat the callbacks already made for the configuration it
+/// starts from.
+pub trait Observer {
+    /// Called for every configuration the run passes through: before each
+    /// step, and once more on the configuration the run ends in.
+    fn on_step(&mut self, _machine: &Machine) -> io::Result<ControlFlow<()>> {
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Called just before a step writes `symbol` over a different symbol
+    /// under the head of `tape`. Breaking interrupts the run before the step.
+    fn on_write(
+        &mut self,
+        _machine: &Machine,
+        _tape: usize,
+        _symbol: &str,
+    ) -> io::Result<ControlFlow<()>> {
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Called when a run starts a machine in its initial state, and when a
+    /// step moves it to a different state, but not on a step that stays in
+    /// the same state. It comes before `on_step` for that configuration.
+    fn on_state_enter(&mut self, _machine: &Machine) -> io::Result<ControlFlow<()>> {
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Called once when the machine stops or hits a limit, but not when an
+    /// observer interrupts the run.
+    fn on_halt(&mut self, _machine: &Machine, _result: &RunResult) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Observes nothing, for runs that only want the result.
+impl Observer for () {}
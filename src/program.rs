@@ -0,0 +1,495 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use crate::{
+    BLANK, LEFT_END, RIGHT_END, StateId, SymbolId,
+    tape::Step,
+    turd::{Halting, Pattern, Rule, SymbolClass, TurdFile},
+};
+
+#[derive(Clone, Default)]
+pub(crate) struct Interner {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+}
+
+impl Interner {
+    pub(crate) fn intern(&mut self, name: &str) -> usize {
+        if let Some(id) = self.get(name) {
+            return id;
+        }
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).copied()
+    }
+
+    pub(crate) fn name(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.names.len()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct Transition {
+    pub(crate) line: usize,
+    // Index into `Program::actions` of the write and step for each tape.
+    pub(crate) action: usize,
+    pub(crate) next: StateId,
+}
+
+#[derive(Clone)]
+pub(crate) enum Slots {
+    // Indexed by the state followed by the read symbols as digits in base
+    // `symbols.len()`. Ranges are halved to `u32` to keep the table small.
+    Dense(Vec<(u32, u32)>),
+    // Used instead when the dense table would be too large, e.g. for many tapes.
+    Sparse(HashMap<(StateId, Vec<SymbolId>), (usize, usize)>),
+}
+
+#[derive(Clone)]
+pub(crate) enum Matcher {
+    Literal(SymbolId),
+    // Whether the class contains each symbol of the program, with the class
+    // itself kept for tape symbols the program never mentions.
+    Class(Vec<bool>, SymbolClass),
+    Any,
+}
+
+#[derive(Clone)]
+pub(crate) struct PatternRule {
+    pub(crate) matchers: Vec<Matcher>,
+    pub(crate) specificity: (usize, usize),
+    pub(crate) transition: Transition,
+}
+
+impl PatternRule {
+    pub(crate) fn matches<'a>(
+        &self,
+        read: impl Fn(usize) -> SymbolId,
+        name: impl Fn(SymbolId) -> &'a str,
+    ) -> bool {
+        self.matchers.iter().enumerate().all(|(n, matcher)| {
+            let symbol = read(n);
+            match matcher {
+                Matcher::Literal(literal) => symbol == *literal,
+                Matcher::Class(members, class) => match members.get(symbol) {
+                    Some(&member) => member,
+                    None => class.contains(name(symbol)),
+                },
+                Matcher::Any => true,
+            }
+        })
+    }
+}
+
+/// A compiled machine, with its transitions in lookup tables.
+#[derive(Clone)]
+pub struct Program {
+    pub(crate) states: Interner,
+    pub(crate) symbols: Interner,
+    pub(crate) tapes: usize,
+    // Transitions grouped by state and read symbols in file order, with
+    // `slots` holding the range of each group.
+    pub(crate) transitions: Vec<Transition>,
+    // The symbol to write, or `None` to leave the cell unchanged, and the step.
+    pub(crate) actions: Vec<(Option<SymbolId>, Step)>,
+    pub(crate) slots: Slots,
+    // Transitions reading a class or `*` on some tape, by state and in order
+    // of precedence. They are only tried when no literal transition matches.
+    pub(crate) patterns: Vec<Vec<PatternRule>>,
+    pub(crate) halting: Vec<Option<Halting>>,
+    pub(crate) initial: Option<StateId>,
+    pub(crate) closed_alphabet: bool,
+    pub(crate) blank: SymbolId,
+    pub(crate) left_end: SymbolId,
+    pub(crate) right_end: SymbolId,
+}
+
+impl Program {
+    /// Compiles a parsed file, expanding its variables.
+    pub fn compile(file: &TurdFile) -> Self {
+        let rules = file.rules();
+        let mut states = Interner::default();
+        let mut symbols = Interner::default();
+        let blank = symbols.intern(file.header.blank.unwrap_or(BLANK));
+        let left_end = symbols.intern(LEFT_END);
+        let right_end = symbols.intern(RIGHT_END);
+        for &symbol in file.header.alphabet.iter().flatten() {
+            symbols.intern(symbol);
+        }
+        let initial = file.header.initial.map(|state| states.intern(state));
+        for &symbol in file.header.sets.iter().flat_map(|(_, set)| set) {
+            symbols.intern(symbol);
+        }
+        let reads = rules.iter().map(|rule| {
+            let read = rule
+                .read
+                .iter()
+                .map(|read| file.header.pattern(read).unwrap());
+            read.collect::<Vec<_>>()
+        });
+        let reads = reads.collect::<Vec<_>>();
+        for (rule, reads) in rules.iter().zip(&reads) {
+            states.intern(&rule.current);
+            states.intern(&rule.next);
+            for pattern in reads {
+                if let Pattern::Literal(symbol) = pattern {
+                    symbols.intern(symbol);
+                }
+            }
+            for symbol in rule.write.iter().filter(|&write| write != "*") {
+                symbols.intern(symbol);
+            }
+        }
+        for &(state, _) in &file.header.halting {
+            states.intern(state);
+        }
+
+        let tapes = file.tapes();
+        let mut actions = Vec::with_capacity(rules.len() * tapes);
+        let mut action = |rule: &Rule| {
+            let writes =
+                (rule.write.iter()).map(|write| symbols.get(write).filter(|_| write != "*"));
+            actions.extend(writes.zip(rule.turd.step.iter().copied()));
+            Transition {
+                line: rule.turd.line,
+                action: actions.len() - tapes,
+                next: states.get(&rule.next).unwrap(),
+            }
+        };
+        let mut literal = Vec::new();
+        let mut patterns = vec![Vec::new(); states.len()];
+        for (rule, reads) in rules.iter().zip(reads) {
+            let literals = reads.iter().map(|pattern| match pattern {
+                Pattern::Literal(symbol) => symbols.get(symbol),
+                _ => None,
+            });
+            if let Some(reads) = literals.collect::<Option<Vec<_>>>() {
+                let state = states.get(&rule.current).unwrap();
+                literal.push(((state, reads), action(rule)));
+                continue;
+            }
+            let matchers = reads.iter().map(|pattern| match pattern {
+                Pattern::Literal(symbol) => Matcher::Literal(symbols.get(symbol).unwrap()),
+                Pattern::Class(class) => {
+                    let members = (0..symbols.len()).map(|id| class.contains(symbols.name(id)));
+                    Matcher::Class(members.collect(), class.clone())
+                }
+                Pattern::Any => Matcher::Any,
+            });
+            patterns[states.get(&rule.current).unwrap()].push(PatternRule {
+                matchers: matchers.collect(),
+                specificity: Pattern::specificity(&reads),
+                transition: action(rule),
+            });
+        }
+        // Stable sorts keep file order among equals, so the first matching
+        // turd still wins in deterministic runs.
+        for rules in &mut patterns {
+            rules.sort_by_key(|rule| Reverse(rule.specificity));
+        }
+        literal.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut ranges = Vec::<((StateId, Vec<SymbolId>), (usize, usize))>::new();
+        let mut transitions = Vec::with_capacity(literal.len());
+        for (key, transition) in literal {
+            match ranges.last_mut() {
+                Some((last, range)) if *last == key => range.1 += 1,
+                _ => ranges.push((key, (transitions.len(), transitions.len() + 1))),
+            }
+            transitions.push(transition);
+        }
+        let size = (symbols.len().checked_pow(tapes as u32))
+            .and_then(|size| size.checked_mul(states.len()))
+            .filter(|&size| size <= 1 << 20 && transitions.len() <= u32::MAX as usize);
+        let slots = match size {
+            Some(size) => {
+                let mut slots = vec![(0, 0); size];
+                for ((state, reads), range) in ranges {
+                    let index = reads
+                        .iter()
+                        .fold(state, |i, &read| i * symbols.len() + read);
+                    slots[index] = (range.0 as u32, range.1 as u32);
+                }
+                Slots::Dense(slots)
+            }
+            None => Slots::Sparse(ranges.into_iter().collect()),
+        };
+
+        let mut halting = vec![None; states.len()];
+        for &(state, halt) in &file.header.halting {
+            halting[states.intern(state)] = Some(halt);
+        }
+
+        Self {
+            states,
+            symbols,
+            tapes,
+            transitions,
+            actions,
+            slots,
+            patterns,
+            halting,
+            initial,
+            closed_alphabet: file.header.alphabet.is_some(),
+            blank,
+            left_end,
+            right_end,
+        }
+    }
+
+    /// The number of tapes each transition reads.
+    pub fn tapes(&self) -> usize {
+        self.tapes
+    }
+
+    /// The number of states.
+    pub fn states(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Whether a transition or directive names `state`.
+    pub fn has_state(&self, state: &str) -> bool {
+        self.states.get(state).is_some()
+    }
+
+    /// The state declared with `initial:`, if any.
+    pub fn initial_state(&self) -> Option<&str> {
+        self.initial.map(|state| self.states.name(state))
+    }
+
+    /// The blank symbol.
+    pub fn blank(&self) -> &str {
+        self.symbols.name(self.blank)
+    }
+
+    pub(crate) fn literal(
+        &self,
+        state: StateId,
+        reads: impl Iterator<Item = SymbolId>,
+    ) -> &[Transition] {
+        let (start, end) = match &self.slots {
+            Slots::Dense(slots) => {
+                let mut index = state;
+                for read in reads {
+                    if read >= self.symbols.len() {
+                        return &[];
+                    }
+                    index = index * self.symbols.len() + read;
+                }
+                let (start, end) = slots[index];
+                (start as usize, end as usize)
+            }
+            Slots::Sparse(slots) => match slots.get(&(state, reads.collect())) {
+                Some(&range) => range,
+                None => return &[],
+            },
+        };
+        &self.transitions[start..end]
+    }
+
+    // Every transition of the highest precedence that matches, in file order.
+    pub(crate) fn transitions<'a>(
+        &self,
+        state: StateId,
+        read: impl Fn(usize) -> SymbolId,
+        name: impl Fn(SymbolId) -> &'a str,
+    ) -> Vec<Transition> {
+        let literal = self.literal(state, (0..self.tapes).map(&read));
+        if !literal.is_empty() {
+            return literal.to_vec();
+        }
+        let mut rules = self.patterns[state]
+            .iter()
+            .filter(|rule| rule.matches(&read, &name))
+            .peekable();
+        let Some(specificity) = rules.peek().map(|rule| rule.specificity) else {
+            return Vec::new();
+        };
+        rules
+            .take_while(|rule| rule.specificity == specificity)
+            .map(|rule| rule.transition)
+            .collect()
+    }
+
+    pub(crate) fn transition<'a>(
+        &self,
+        state: StateId,
+        read: impl Fn(usize) -> SymbolId,
+        name: impl Fn(SymbolId) -> &'a str,
+    ) -> Option<Transition> {
+        match self.literal(state, (0..self.tapes).map(&read)).first() {
+            Some(&transition) => Some(transition),
+            None => (self.patterns[state].iter())
+                .find(|rule| rule.matches(&read, &name))
+                .map(|rule| rule.transition),
+        }
+    }
+
+    pub(crate) fn actions(&self, transition: &Transition) -> &[(Option<SymbolId>, Step)] {
+        &self.actions[transition.action..transition.action + self.tapes]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(source: &str) -> Program {
+        Program::compile(&TurdFile::parse("test.turd", source).unwrap())
+    }
+
+    // The line of the transition the program takes, if any. Symbols the
+    // program never mentions are numbered after its own, as in a machine.
+    fn lookup(program: &Program, state: &str, reads: &[&str]) -> Option<usize> {
+        let state = program.states.get(state)?;
+        let known = program.symbols.len();
+        let ids = (reads.iter().enumerate())
+            .map(|(n, read)| program.symbols.get(read).unwrap_or(known + n))
+            .collect::<Vec<_>>();
+        let name = |symbol: SymbolId| match symbol.checked_sub(known) {
+            Some(n) => reads[n],
+            None => program.symbols.name(symbol),
+        };
+        let transition = program.transition(state, |n| ids[n], name);
+        transition.map(|transition| transition.line)
+    }
+
+    // How transitions were found before the lookup tables: the first one
+    // in file order whose state and read symbols match.
+    fn first_match(file: &TurdFile, state: &str, reads: &[&str]) -> Option<usize> {
+        let turd =
+            (file.turds().iter()).find(|turd| turd.current() == state && turd.read() == reads);
+        turd.map(|turd| turd.line())
+    }
+
+    fn assert_same_as_first_match(source: &str, states: &[&str], symbols: &[&str]) {
+        let file = TurdFile::parse("test.turd", source).unwrap();
+        let program = Program::compile(&file);
+        let tapes = program.tapes();
+        for &state in states {
+            for i in 0..symbols.len().pow(tapes as u32) {
+                let reads =
+                    (0..tapes).map(|n| symbols[i / symbols.len().pow(n as u32) % symbols.len()]);
+                let reads = reads.collect::<Vec<_>>();
+                assert_eq!(
+                    lookup(&program, state, &reads),
+                    first_match(&file, state, &reads),
+                    "{state} reading {reads:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dense_lookup_matches_first_match() {
+        let source = "\
+q0 1 1 R q0
+q0 0 1 L q1
+q0 1 0 R q1
+q1 _ 1 S q0
+q1 0 0 R q1
+";
+        assert!(matches!(compile(source).slots, Slots::Dense(_)));
+        assert_same_as_first_match(source, &["q0", "q1"], &["0", "1", "_"]);
+    }
+
+    #[test]
+    fn sparse_lookup_matches_first_match() {
+        // Twelve tapes need too many slots for the dense table.
+        let tuple = |symbol: &str| [symbol; 12].join(",");
+        let steps = tuple("R");
+        let source = format!(
+            "q {} {} {steps} q\nq {} {} {steps} r\nq {} {} {steps} r\nr {} {} {steps} q\n",
+            tuple("a"),
+            tuple("b"),
+            tuple("b"),
+            tuple("a"),
+            tuple("a"),
+            tuple("_"),
+            tuple("_"),
+            tuple("a"),
+        );
+        assert!(matches!(compile(&source).slots, Slots::Sparse(_)));
+        let file = TurdFile::parse("test.turd", &source).unwrap();
+        let program = Program::compile(&file);
+        for state in ["q", "r"] {
+            for symbol in ["a", "b", "_"] {
+                let reads = vec![symbol; 12];
+                assert_eq!(
+                    lookup(&program, state, &reads),
+                    first_match(&file, state, &reads)
+                );
+            }
+        }
+        let mixed = ["a", "b"].repeat(6);
+        assert_eq!(lookup(&program, "q", &mixed), None);
+    }
+
+    #[test]
+    fn literals_take_precedence_over_classes_over_any() {
+        let program = compile(
+            "\
+set: vowel a e
+q * x S h
+q [0-9] y S h
+q @vowel z S h
+q 5 w S h
+q [5-7] v S h
+",
+        );
+        assert_eq!(lookup(&program, "q", &["5"]), Some(5));
+        assert_eq!(lookup(&program, "q", &["6"]), Some(3));
+        assert_eq!(lookup(&program, "q", &["e"]), Some(4));
+        assert_eq!(lookup(&program, "q", &["w"]), Some(2));
+    }
+
+    #[test]
+    fn any_written_keeps_the_symbol_read() {
+        let program = compile("alphabet: * 1\nset: s *\nq 1 * R q\n");
+        let state = program.states.get("q").unwrap();
+        let one = program.symbols.get("1").unwrap();
+        let transition = program.transition(state, |_| one, |_| "1").unwrap();
+        assert_eq!(program.actions(&transition)[0].0, None);
+    }
+
+    #[test]
+    fn more_literal_tapes_take_precedence() {
+        let program = compile(
+            "\
+q [01],[01] a,a S,S h
+q *,1 b,b S,S h
+q 1,* c,c S,S h
+q *,* d,d S,S h
+",
+        );
+        assert_eq!(lookup(&program, "q", &["1", "1"]), Some(2));
+        assert_eq!(lookup(&program, "q", &["0", "0"]), Some(1));
+        assert_eq!(lookup(&program, "q", &["1", "x"]), Some(3));
+        assert_eq!(lookup(&program, "q", &["x", "x"]), Some(4));
+    }
+
+    #[test]
+    fn nondeterministic_lookup_returns_the_most_specific_group() {
+        let program = compile("q 1 1 R a\nq * 1 R b\nq 1 1 R c\nq 0 1 R d\n");
+        let state = program.states.get("q").unwrap();
+        let one = program.symbols.get("1").unwrap();
+        let name = |symbol| program.symbols.name(symbol);
+        let lines = program.transitions(state, |_| one, name);
+        let lines = lines.iter().map(|t| t.line).collect::<Vec<_>>();
+        assert_eq!(lines, [1, 3]);
+    }
+
+    #[test]
+    fn variables_expand_over_the_alphabet() {
+        let program = compile("alphabet: 0 1\nq $x $x R q\nq _ _ S h\n");
+        assert_eq!(lookup(&program, "q", &["0"]), Some(2));
+        assert_eq!(lookup(&program, "q", &["1"]), Some(2));
+        assert_eq!(lookup(&program, "q", &["_"]), Some(3));
+    }
+}
@@ -0,0 +1,355 @@
+use std::{collections::VecDeque, fmt::Display, ops::Range};
+
+use crate::{SymbolId, TuringMachineError};
+
+/// A head move, written `L`, `R`, `L2`, `R3` and so on, or `S` or `N` to
+/// stay in place.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    /// Move left this many cells.
+    Left(usize),
+    /// Move right this many cells.
+    Right(usize),
+    /// Stay on the same cell.
+    Stay,
+}
+
+impl TryFrom<&str> for Step {
+    type Error = TuringMachineError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let error = || {
+            TuringMachineError::Transformation(format!(
+                "{value} is not a valid step. Expected 'L', 'R', 'S' or 'N', \
+                 with an optional count for 'L' and 'R' such as 'R3'"
+            ))
+        };
+        let (direction, count) = value.split_at(value.chars().next().map_or(0, char::len_utf8));
+        let count = match count {
+            "" => 1,
+            _ if count.starts_with(|c: char| c.is_ascii_digit()) => {
+                count.parse().ok().filter(|&n| n > 0).ok_or_else(error)?
+            }
+            _ => return Err(error()),
+        };
+        match direction {
+            "L" => Ok(Self::Left(count)),
+            "R" => Ok(Self::Right(count)),
+            "S" | "N" if value.len() == 1 => Ok(Self::Stay),
+            _ => Err(error()),
+        }
+    }
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Left(1) => write!(f, "L"),
+            Self::Right(1) => write!(f, "R"),
+            Self::Left(n) => write!(f, "L{n}"),
+            Self::Right(n) => write!(f, "R{n}"),
+            Self::Stay => write!(f, "S"),
+        }
+    }
+}
+
+/// What a semi-infinite tape does when the head moves off its left edge.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LeftEdge {
+    /// The head stays on the first cell.
+    Stay,
+    /// The run fails with a tape error.
+    Error,
+}
+
+/// How a tape behaves at its ends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TapeMode {
+    /// Grows with blanks in both directions.
+    Infinite,
+    /// Grows to the right only.
+    SemiInfinite(LeftEdge),
+    /// Holds only the input, between end markers `<` and `>`.
+    Bounded,
+    /// Wraps around from either end of the input to the other.
+    Circular,
+}
+
+impl TryFrom<&str> for TapeMode {
+    type Error = TuringMachineError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "infinite" => Ok(Self::Infinite),
+            "semi-infinite" | "semi-infinite-stay" => Ok(Self::SemiInfinite(LeftEdge::Stay)),
+            "semi-infinite-error" => Ok(Self::SemiInfinite(LeftEdge::Error)),
+            "bounded" => Ok(Self::Bounded),
+            "circular" => Ok(Self::Circular),
+            _ => Err(TuringMachineError::Transformation(format!(
+                "{value} is not a valid tape mode. Expected 'infinite', 'semi-infinite-stay', \
+                 'semi-infinite-error', 'bounded' or 'circular'"
+            ))),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Tape {
+    pub(crate) cells: VecDeque<SymbolId>,
+    origin: usize,
+    pub(crate) head: isize,
+    mode: TapeMode,
+    blank: SymbolId,
+}
+
+impl Tape {
+    pub(crate) fn new(
+        mode: TapeMode,
+        blank: SymbolId,
+        ends: [SymbolId; 2],
+        symbols: impl IntoIterator<Item = SymbolId>,
+        head: usize,
+    ) -> Self {
+        let mut cells = symbols.into_iter().collect::<VecDeque<_>>();
+        let mut origin = 0;
+        match mode {
+            TapeMode::Bounded => {
+                cells.push_front(ends[0]);
+                cells.push_back(ends[1]);
+                origin = 1;
+            }
+            _ if cells.is_empty() => cells.push_back(blank),
+            _ => {}
+        }
+        Self {
+            cells,
+            origin,
+            head: head as isize,
+            mode,
+            blank,
+        }
+    }
+
+    pub(crate) fn index(&self) -> usize {
+        (self.origin as isize + self.head) as usize
+    }
+
+    pub(crate) fn read(&self) -> SymbolId {
+        self.cells[self.index()]
+    }
+
+    // The symbol at a head position, with cells not yet reached read as blank.
+    pub(crate) fn cell(&self, position: isize) -> SymbolId {
+        let index = self.origin as isize + position;
+        match usize::try_from(index).ok().and_then(|i| self.cells.get(i)) {
+            Some(&cell) => cell,
+            None => self.blank,
+        }
+    }
+
+    // From the first to the last non-blank cell, or empty at the head when
+    // every cell is blank. The end markers of a bounded tape do not count.
+    pub(crate) fn written(&self) -> Range<usize> {
+        let inside = match self.mode {
+            TapeMode::Bounded => 1..self.cells.len() - 1,
+            _ => 0..self.cells.len(),
+        };
+        let written = |&cell: &SymbolId| cell != self.blank;
+        let mut cells = self.cells.range(inside.clone());
+        match (cells.clone().position(written), cells.rposition(written)) {
+            (Some(start), Some(end)) => inside.start + start..inside.start + end + 1,
+            _ => self.index()..self.index(),
+        }
+    }
+
+    // The cells worth printing: the written ones, widened to take in the head,
+    // or the whole of a bounded tape.
+    pub(crate) fn visible(&self) -> Range<usize> {
+        if self.mode == TapeMode::Bounded {
+            return 0..self.cells.len();
+        }
+        let (written, head) = (self.written(), self.index());
+        written.start.min(head)..written.end.max(head + 1)
+    }
+
+    // Fails if writing `write` and then taking `step` would break the rules
+    // of the tape mode, without changing anything, so that a step over
+    // several tapes either happens on all of them or on none.
+    pub(crate) fn check(
+        &self,
+        write: Option<SymbolId>,
+        step: Step,
+    ) -> Result<(), TuringMachineError> {
+        if let TapeMode::Infinite | TapeMode::Circular | TapeMode::SemiInfinite(LeftEdge::Stay) =
+            self.mode
+        {
+            return Ok(());
+        }
+        let index = self.index();
+        let last = self.cells.len() - 1;
+        let error = |message: &str| Err(TuringMachineError::Tape(message.to_string()));
+        match (self.mode, step) {
+            // The end markers are the first and last cells, whatever symbols
+            // the input puts between them.
+            (TapeMode::Bounded, _)
+                if (index == 0 || index == last)
+                    && write.is_some_and(|write| write != self.cells[index]) =>
+            {
+                Err(TuringMachineError::Tape(format!(
+                    "cannot overwrite the end marker at cell {}",
+                    self.head
+                )))
+            }
+            (TapeMode::SemiInfinite(LeftEdge::Error), Step::Left(n)) if n > index => {
+                error("head moved off the left edge of a semi-infinite tape")
+            }
+            (TapeMode::Bounded, Step::Left(n)) if n > index => {
+                error("head moved past the left end marker")
+            }
+            (TapeMode::Bounded, Step::Right(n)) if index + n > last => {
+                error("head moved past the right end marker")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Writes and moves assume `check` has passed.
+    pub(crate) fn write(&mut self, symbol: SymbolId) {
+        let index = self.index();
+        self.cells[index] = symbol;
+    }
+
+    pub(crate) fn step(&mut self, step: Step) {
+        match step {
+            Step::Left(n) => (0..n).for_each(|_| self.move_left()),
+            Step::Right(n) => (0..n).for_each(|_| self.move_right()),
+            Step::Stay => {}
+        }
+    }
+
+    fn move_left(&mut self) {
+        match self.mode {
+            _ if self.index() > 0 => self.head -= 1,
+            TapeMode::Infinite => {
+                self.cells.push_front(self.blank);
+                self.origin += 1;
+                self.head -= 1;
+            }
+            TapeMode::Circular => self.head = (self.cells.len() - 1) as isize,
+            TapeMode::SemiInfinite(_) | TapeMode::Bounded => {}
+        }
+    }
+
+    fn move_right(&mut self) {
+        match self.mode {
+            _ if self.index() + 1 < self.cells.len() => self.head += 1,
+            TapeMode::Infinite | TapeMode::SemiInfinite(_) => {
+                self.cells.push_back(self.blank);
+                self.head += 1;
+            }
+            TapeMode::Circular => self.head = 0,
+            TapeMode::Bounded => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLANK: SymbolId = 0;
+    const ENDS: [SymbolId; 2] = [1, 2];
+
+    // A tape holding 3 4 5 with the head on the first cell.
+    fn tape(mode: TapeMode) -> Tape {
+        Tape::new(mode, BLANK, ENDS, [3, 4, 5], 0)
+    }
+
+    fn apply(
+        tape: &mut Tape,
+        write: Option<SymbolId>,
+        step: Step,
+    ) -> Result<(), TuringMachineError> {
+        tape.check(write, step)?;
+        if let Some(write) = write {
+            tape.write(write);
+        }
+        tape.step(step);
+        Ok(())
+    }
+
+    #[test]
+    fn infinite_tape_grows_both_ways() {
+        let mut tape = tape(TapeMode::Infinite);
+        apply(&mut tape, None, Step::Left(2)).unwrap();
+        assert_eq!((tape.head, tape.read()), (-2, BLANK));
+        apply(&mut tape, Some(6), Step::Right(6)).unwrap();
+        assert_eq!((tape.head, tape.read()), (4, BLANK));
+        assert_eq!(tape.cell(-2), 6);
+        assert_eq!(tape.cell(2), 5);
+        assert_eq!(tape.cell(100), BLANK);
+        assert_eq!(tape.written(), 0..5);
+    }
+
+    #[test]
+    fn semi_infinite_tape_stays_at_the_left_edge() {
+        let mut tape = tape(TapeMode::SemiInfinite(LeftEdge::Stay));
+        apply(&mut tape, None, Step::Right(1)).unwrap();
+        apply(&mut tape, None, Step::Left(3)).unwrap();
+        assert_eq!((tape.head, tape.read()), (0, 3));
+        apply(&mut tape, None, Step::Right(4)).unwrap();
+        assert_eq!((tape.head, tape.read()), (4, BLANK));
+    }
+
+    #[test]
+    fn semi_infinite_tape_fails_off_the_left_edge() {
+        let mut tape = tape(TapeMode::SemiInfinite(LeftEdge::Error));
+        apply(&mut tape, None, Step::Right(1)).unwrap();
+        assert!(apply(&mut tape, Some(7), Step::Left(2)).is_err());
+        // A failed check leaves the tape alone.
+        assert_eq!((tape.head, tape.read()), (1, 4));
+        apply(&mut tape, None, Step::Left(1)).unwrap();
+        assert_eq!(tape.head, 0);
+    }
+
+    #[test]
+    fn bounded_tape_stops_at_its_end_markers() {
+        let mut tape = tape(TapeMode::Bounded);
+        apply(&mut tape, None, Step::Left(1)).unwrap();
+        assert_eq!((tape.head, tape.read()), (-1, ENDS[0]));
+        assert!(apply(&mut tape, None, Step::Left(1)).is_err());
+        assert!(apply(&mut tape, Some(3), Step::Stay).is_err());
+        apply(&mut tape, Some(ENDS[0]), Step::Right(4)).unwrap();
+        assert_eq!((tape.head, tape.read()), (3, ENDS[1]));
+        assert!(apply(&mut tape, None, Step::Right(1)).is_err());
+        assert!(apply(&mut tape, None, Step::Left(5)).is_err());
+        apply(&mut tape, None, Step::Left(4)).unwrap();
+        assert_eq!(tape.read(), ENDS[0]);
+    }
+
+    #[test]
+    fn bounded_tape_overwrites_marker_symbols_inside_it() {
+        let mut tape = Tape::new(TapeMode::Bounded, BLANK, ENDS, [ENDS[1], ENDS[0]], 0);
+        apply(&mut tape, Some(3), Step::Right(1)).unwrap();
+        apply(&mut tape, Some(4), Step::Stay).unwrap();
+        assert_eq!(tape.cells, [ENDS[0], 3, 4, ENDS[1]]);
+        assert_eq!(tape.written(), 1..3);
+        assert_eq!(tape.visible(), 0..4);
+    }
+
+    #[test]
+    fn circular_tape_wraps_around() {
+        let mut tape = tape(TapeMode::Circular);
+        apply(&mut tape, None, Step::Left(1)).unwrap();
+        assert_eq!((tape.head, tape.read()), (2, 5));
+        apply(&mut tape, None, Step::Right(2)).unwrap();
+        assert_eq!((tape.head, tape.read()), (1, 4));
+        assert_eq!(tape.cells.len(), 3);
+    }
+
+    #[test]
+    fn empty_tapes_start_with_a_blank_cell() {
+        let tape = Tape::new(TapeMode::Infinite, BLANK, ENDS, [], 0);
+        assert_eq!(tape.read(), BLANK);
+        assert_eq!(tape.written(), 0..0);
+        assert_eq!(tape.visible(), 0..1);
+    }
+}
@@ -0,0 +1,876 @@
+use std::{collections::BTreeSet, fmt::Display};
+
+use crate::{
+    BLANK, State, Symbol, TuringMachineError,
+    error::{Diagnostic, SpanError},
+    input::{Input, split_directive_comment},
+    tape::Step,
+};
+
+/// How a state declared with `accept:`, `reject:` or `halt:` ends a run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Halting {
+    /// Accepts the input.
+    Accept,
+    /// Rejects the input.
+    Reject,
+    /// Stops without accepting or rejecting, for machines that compute.
+    Halt,
+}
+
+#[derive(Default)]
+pub(crate) struct Header<'a> {
+    pub(crate) tapes: Option<usize>,
+    pub(crate) initial: Option<State<'a>>,
+    pub(crate) blank: Option<Symbol<'a>>,
+    pub(crate) alphabet: Option<Vec<Symbol<'a>>>,
+    pub(crate) sets: Vec<(&'a str, Vec<Symbol<'a>>)>,
+    pub(crate) halting: Vec<(State<'a>, Halting)>,
+}
+
+impl<'a> Header<'a> {
+    fn parse_directive(&mut self, s: &'a str) -> Result<(), SpanError<'a>> {
+        let error = |span, message| SpanError { span, message };
+        let mut tokens = s.split_whitespace();
+        let keyword = tokens.next().unwrap();
+        let args = tokens.collect::<Vec<_>>();
+        if args.is_empty() {
+            return Err(error(s, format!("{keyword} expects at least one argument")));
+        }
+        let duplicate = || error(keyword, format!("{keyword} is declared more than once"));
+        match keyword {
+            "tapes:" => {
+                let [value] = args[..] else {
+                    return Err(error(s, format!("{keyword} expects exactly one argument")));
+                };
+                let tapes = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    error(value, format!("{value} is not a positive number of tapes"))
+                })?;
+                if self.tapes.replace(tapes).is_some() {
+                    return Err(duplicate());
+                }
+            }
+            "initial:" | "blank:" => {
+                let [value] = args[..] else {
+                    return Err(error(s, format!("{keyword} expects exactly one argument")));
+                };
+                let slot = match keyword {
+                    "initial:" => &mut self.initial,
+                    _ => &mut self.blank,
+                };
+                if slot.replace(value).is_some() {
+                    return Err(duplicate());
+                }
+            }
+            "alphabet:" => {
+                if self.alphabet.replace(args).is_some() {
+                    return Err(duplicate());
+                }
+            }
+            "set:" => {
+                let (name, symbols) = (args[0], &args[1..]);
+                if symbols.is_empty() {
+                    return Err(error(
+                        s,
+                        format!("{keyword} expects a name and its symbols"),
+                    ));
+                }
+                if self.sets.iter().any(|&(set, _)| set == name) {
+                    return Err(error(
+                        name,
+                        format!("Set {name} is declared more than once"),
+                    ));
+                }
+                self.sets.push((name, symbols.to_vec()));
+            }
+            "accept:" | "reject:" | "halt:" => {
+                let halt = match keyword {
+                    "accept:" => Halting::Accept,
+                    "reject:" => Halting::Reject,
+                    _ => Halting::Halt,
+                };
+                for state in args {
+                    let declared = self.halting.iter().find(|&&(other, _)| other == state);
+                    match declared {
+                        Some(&(_, other)) if other != halt => {
+                            let other = match other {
+                                Halting::Accept => "accept:",
+                                Halting::Reject => "reject:",
+                                Halting::Halt => "halt:",
+                            };
+                            return Err(error(
+                                state,
+                                format!("State {state} is already declared with {other}"),
+                            ));
+                        }
+                        Some(_) => {}
+                        None => self.halting.push((state, halt)),
+                    }
+                }
+            }
+            _ => return Err(error(keyword, format!("Unknown directive {keyword}"))),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn pattern<'s>(&self, symbol: &'s str) -> Result<Pattern<'s>, String> {
+        if symbol == "*" {
+            return Ok(Pattern::Any);
+        }
+        if let Some(name) = symbol.strip_prefix('@').filter(|name| !name.is_empty()) {
+            let (_, members) = (self.sets.iter().find(|&&(set, _)| set == name))
+                .ok_or_else(|| format!("No set named {name} is declared with set:"))?;
+            let members = members.iter().map(|member| member.to_string());
+            return Ok(Pattern::Class(SymbolClass::Set(members.collect())));
+        }
+        let class = symbol.strip_prefix('[').and_then(|s| s.strip_suffix(']'));
+        let Some(class) = class.filter(|class| !class.is_empty()) else {
+            return Ok(Pattern::Literal(symbol));
+        };
+        let (negated, class) = match class.strip_prefix('^') {
+            Some(rest) if !rest.is_empty() => (true, rest),
+            _ => (false, class),
+        };
+        let chars = class.chars().collect::<Vec<_>>();
+        let mut ranges = Vec::new();
+        let mut rest = &chars[..];
+        loop {
+            rest = match *rest {
+                [] => break,
+                [start, '-', end, ref tail @ ..] => {
+                    if start > end {
+                        return Err(format!("{start}-{end} is not a valid range in {symbol}"));
+                    }
+                    ranges.push((start, end));
+                    tail
+                }
+                [c, ref tail @ ..] => {
+                    ranges.push((c, c));
+                    tail
+                }
+            };
+        }
+        Ok(Pattern::Class(SymbolClass::Chars { negated, ranges }))
+    }
+}
+
+// How a transition matches the symbol under a head, in the order of
+// precedence given on `TurdFile`.
+pub(crate) enum Pattern<'a> {
+    Literal(Symbol<'a>),
+    Class(SymbolClass),
+    Any,
+}
+
+impl Pattern<'_> {
+    pub(crate) fn matches(&self, symbol: &str) -> bool {
+        match self {
+            Self::Literal(literal) => *literal == symbol,
+            Self::Class(class) => class.contains(symbol),
+            Self::Any => true,
+        }
+    }
+
+    pub(crate) fn specificity(patterns: &[Self]) -> (usize, usize) {
+        let count = |f: fn(&Self) -> bool| patterns.iter().filter(|p| f(p)).count();
+        (
+            count(|p| matches!(p, Self::Literal(_))),
+            count(|p| matches!(p, Self::Class(_))),
+        )
+    }
+}
+
+#[derive(Clone)]
+pub(crate) enum SymbolClass {
+    // Single-character symbols within any of the ranges, or outside all of
+    // them when negated.
+    Chars {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+    Set(Vec<String>),
+}
+
+impl SymbolClass {
+    pub(crate) fn contains(&self, symbol: &str) -> bool {
+        match self {
+            Self::Chars { negated, ranges } => {
+                let mut chars = symbol.chars();
+                let within = match (chars.next(), chars.next()) {
+                    (Some(c), None) => ranges
+                        .iter()
+                        .any(|&(start, end)| (start..=end).contains(&c)),
+                    _ => false,
+                };
+                within != *negated
+            }
+            Self::Set(members) => members.iter().any(|member| member == symbol),
+        }
+    }
+}
+
+enum Line<'a> {
+    Blank,
+    Comment(&'a str),
+    Directive(Vec<&'a str>, Option<&'a str>),
+    Turd(usize, Option<&'a str>),
+}
+
+// A `#` token starts a comment where a state name or the end of the line is
+// expected, never where a symbol is, so `#` stays usable as a tape symbol.
+pub(crate) fn split_comment(line: &str, skip: usize) -> (&str, Option<&str>) {
+    let comment = line
+        .split_whitespace()
+        .skip(skip)
+        .find(|token| token.starts_with('#'))
+        .map(|token| token.as_ptr() as usize - line.as_ptr() as usize);
+    match comment {
+        Some(offset) => (line[..offset].trim_end(), Some(&line[offset..])),
+        None => (line, None),
+    }
+}
+
+const TEST_DIRECTIVES: [&str; 8] = [
+    "test:",
+    "state:",
+    "tape:",
+    "track:",
+    "expect:",
+    "final:",
+    "output:",
+    "max-steps:",
+];
+
+/// A parsed `.turd` file: its header directives, transitions and inline tests.
+///
+/// A transition reads a literal symbol, a class such as `[0-9]`, `[^ab]` or
+/// `@name` for a set declared with `set:`, or the `*` wildcard on each tape.
+/// When several transitions match, a literal wins over a class, which wins
+/// over `*`. Across tapes, the transition with more literals wins, then the
+/// one with more classes, and ties go to the one that comes first in the file.
+pub struct TurdFile<'a> {
+    pub(crate) source: &'a str,
+    pub(crate) header: Header<'a>,
+    pub(crate) turds: Vec<Turd<'a>>,
+    // Inputs from `test:` blocks, written in the tape file format.
+    pub(crate) tests: Vec<Input>,
+    lines: Vec<Line<'a>>,
+}
+
+impl<'a> TurdFile<'a> {
+    /// Parses `content`, naming `filepath` in any error.
+    pub fn parse(filepath: &str, content: &'a str) -> Result<Self, TuringMachineError> {
+        let mut header = Header::default();
+        let mut turds = Vec::new();
+        let mut tests = Vec::new();
+        let mut lines = Vec::new();
+        let mut diagnostics = Vec::new();
+        // A test block runs from `test:` over the tape file directives that
+        // follow it, up to the first blank line or anything else.
+        let mut in_test = false;
+        for (i, source) in content.lines().enumerate() {
+            let error = |e: SpanError| Diagnostic::new(filepath, i + 1, source, e.span, e.message);
+            let line = source.trim();
+            let keyword = line.split_whitespace().next();
+            in_test &=
+                line.starts_with('#') || keyword.is_some_and(|k| TEST_DIRECTIVES.contains(&k));
+            if line.is_empty() {
+                lines.push(Line::Blank);
+            } else if line.starts_with('#') {
+                lines.push(Line::Comment(line));
+            } else if in_test || keyword == Some("test:") {
+                in_test = true;
+                let (directive, comment) = split_directive_comment(line);
+                if let Err(e) = Input::parse_directive(&mut tests, directive) {
+                    diagnostics.push(error(e));
+                }
+                lines.push(Line::Directive(
+                    directive.split_whitespace().collect(),
+                    comment,
+                ));
+            } else if line.split_whitespace().next().unwrap().ends_with(':') {
+                let skip = match line.split_whitespace().next() {
+                    Some("blank:") => 2,
+                    Some("alphabet:" | "set:") => usize::MAX,
+                    _ => 1,
+                };
+                let (directive, comment) = split_comment(line, skip);
+                if !turds.is_empty() {
+                    diagnostics.push(error(SpanError {
+                        span: directive,
+                        message: "Directives must come before the first transition".to_string(),
+                    }));
+                    continue;
+                }
+                if let Err(e) = header.parse_directive(directive) {
+                    diagnostics.push(error(e));
+                }
+                lines.push(Line::Directive(
+                    directive.split_whitespace().collect(),
+                    comment,
+                ));
+            } else {
+                let (turd, comment) = split_comment(line, 5);
+                let turd = match Turd::parse_turd((i, turd)) {
+                    Ok(turd) => turd,
+                    Err(e) => {
+                        diagnostics.push(error(e));
+                        continue;
+                    }
+                };
+                let tapes = header
+                    .tapes
+                    .or(turds.first().map(|t: &Turd| t.step.len()))
+                    .unwrap_or(turd.step.len());
+                if turd.step.len() != tapes {
+                    diagnostics.push(error(SpanError {
+                        span: turd.text,
+                        message: format!(
+                            "Expected a transition over {tapes} tape(s) but this one has {}",
+                            turd.step.len()
+                        ),
+                    }));
+                    continue;
+                }
+                let mut literals = Vec::new();
+                for &symbol in turd.read.iter().filter(|&&read| !has_variables(read)) {
+                    match header.pattern(symbol) {
+                        Ok(Pattern::Literal(symbol)) => literals.push(symbol),
+                        Ok(_) => {}
+                        Err(message) => diagnostics.push(error(SpanError {
+                            span: symbol,
+                            message,
+                        })),
+                    }
+                }
+                for &symbol in turd.write.iter().filter(|&&write| !has_variables(write)) {
+                    match header.pattern(symbol) {
+                        Ok(Pattern::Literal(symbol)) => literals.push(symbol),
+                        Ok(Pattern::Any) => {}
+                        _ => diagnostics.push(error(SpanError {
+                            span: symbol,
+                            message: format!(
+                                "{symbol} cannot be written. Write a symbol, or * to leave \
+                                 the cell unchanged"
+                            ),
+                        })),
+                    }
+                }
+                if let Some(alphabet) = &header.alphabet {
+                    let blank = header.blank.unwrap_or(BLANK);
+                    for symbol in literals {
+                        if symbol != blank && !alphabet.contains(&symbol) {
+                            diagnostics.push(error(SpanError {
+                                span: symbol,
+                                message: format!("{symbol} is not in the declared alphabet"),
+                            }));
+                        }
+                    }
+                }
+                turds.push(turd);
+                lines.push(Line::Turd(turds.len() - 1, comment));
+            }
+        }
+
+        let file = Self {
+            source: content,
+            header,
+            turds,
+            tests,
+            lines,
+        };
+        diagnostics.extend(file.template_errors(filepath));
+        if !diagnostics.is_empty() {
+            diagnostics.sort_by_key(|d| d.line);
+            return Err(TuringMachineError::Parse(diagnostics));
+        }
+        Ok(file)
+    }
+
+    // Errors in transitions with `$x` variables, which only show once the
+    // variables have values, such as `@$x` naming a set that does not exist.
+    fn template_errors(&self, filepath: &str) -> Vec<Diagnostic> {
+        let domain = self.domain();
+        let mut diagnostics = Vec::new();
+        let templates = self.turds.iter().filter(|turd| {
+            let tokens = [turd.current, turd.next].into_iter();
+            tokens
+                .chain(turd.read.iter().chain(&turd.write).copied())
+                .any(has_variables)
+        });
+        for turd in templates {
+            let source = self.source.lines().nth(turd.line - 1).unwrap();
+            let error = |span, message| Diagnostic::new(filepath, turd.line, source, span, message);
+            if domain.is_empty() {
+                diagnostics.push(error(
+                    turd.text,
+                    "There are no symbols for the variables to stand for. Declare them with \
+                     alphabet:"
+                        .to_string(),
+                ));
+                continue;
+            }
+            let rules = turd.expand(&domain);
+            for (n, &read) in turd.read.iter().enumerate() {
+                let errors = rules.iter().filter(|_| has_variables(read)).map(|rule| {
+                    let pattern = self.header.pattern(&rule.read[n]);
+                    pattern
+                        .err()
+                        .map(|e| format!("{read} becomes {}. {e}", rule.read[n]))
+                });
+                diagnostics.extend(errors.flatten().next().map(|message| error(read, message)));
+            }
+            for (n, &write) in turd.write.iter().enumerate() {
+                let errors = rules.iter().filter(|_| has_variables(write)).map(|rule| {
+                    match self.header.pattern(&rule.write[n]) {
+                        Ok(Pattern::Literal(_) | Pattern::Any) => None,
+                        _ => Some(format!(
+                            "{write} becomes {}, which cannot be written",
+                            rule.write[n]
+                        )),
+                    }
+                });
+                diagnostics.extend(errors.flatten().next().map(|message| error(write, message)));
+            }
+        }
+        diagnostics
+    }
+
+    /// The number of tapes, from `tapes:` or else the first transition.
+    pub fn tapes(&self) -> usize {
+        self.header
+            .tapes
+            .or(self.turds.first().map(|turd| turd.step.len()))
+            .unwrap_or(1)
+    }
+
+    // What `$x` variables range over: the declared alphabet, or otherwise
+    // every symbol the file mentions, leaving out the blank either way.
+    fn domain(&self) -> Vec<&'a str> {
+        let blank = self.header.blank.unwrap_or(BLANK);
+        let mut domain = match &self.header.alphabet {
+            Some(alphabet) => alphabet.clone(),
+            None => {
+                let sets = self
+                    .header
+                    .sets
+                    .iter()
+                    .flat_map(|(_, set)| set.iter().copied());
+                let symbols = self
+                    .turds
+                    .iter()
+                    .flat_map(|t| t.read.iter().chain(&t.write));
+                let symbols = symbols.copied().filter(|&symbol| {
+                    !has_variables(symbol)
+                        && matches!(self.header.pattern(symbol), Ok(Pattern::Literal(_)))
+                });
+                let symbols = sets.chain(symbols).collect::<BTreeSet<_>>();
+                symbols.into_iter().collect()
+            }
+        };
+        domain.retain(|&symbol| symbol != blank);
+        domain
+    }
+
+    /// The transitions, in file order.
+    pub fn turds(&self) -> &[Turd<'a>] {
+        &self.turds
+    }
+
+    /// The inputs declared in `test:` blocks.
+    pub fn tests(&self) -> &[Input] {
+        &self.tests
+    }
+
+    /// Every state named by a transition, sorted.
+    pub fn states(&self) -> impl Iterator<Item = State<'a>> {
+        self.turds
+            .iter()
+            .flat_map(|t| [t.current, t.next])
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+    }
+
+    pub(crate) fn rules(&self) -> Vec<Rule<'_, 'a>> {
+        let domain = self.domain();
+        self.turds
+            .iter()
+            .flat_map(|turd| turd.expand(&domain))
+            .collect()
+    }
+}
+
+impl Display for TurdFile<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = |field: fn(&Turd) -> usize| self.turds.iter().map(field).max().unwrap_or(0);
+        let current = width(|t| t.current.len());
+        let read = width(|t| t.read.join(",").len());
+        let write = width(|t| t.write.join(",").len());
+        let start = self.lines.iter().position(|l| !matches!(l, Line::Blank));
+        let end = self.lines.iter().rposition(|l| !matches!(l, Line::Blank));
+        let (Some(start), Some(end)) = (start, end) else {
+            return Ok(());
+        };
+        let mut previous_blank = false;
+        for line in &self.lines[start..=end] {
+            let comment = match line {
+                Line::Blank if previous_blank => continue,
+                Line::Blank => None,
+                Line::Comment(comment) => Some(*comment),
+                Line::Directive(tokens, comment) => {
+                    write!(f, "{}", tokens.join(" "))?;
+                    *comment
+                }
+                Line::Turd(i, comment) => {
+                    let turd = &self.turds[*i];
+                    let step = turd.step.iter().map(Step::to_string).collect::<Vec<_>>();
+                    write!(
+                        f,
+                        "{:current$} {:read$} {:write$} {} {}",
+                        turd.current,
+                        turd.read.join(","),
+                        turd.write.join(","),
+                        step.join(","),
+                        turd.next
+                    )?;
+                    *comment
+                }
+            };
+            previous_blank = matches!(line, Line::Blank);
+            match (line, comment) {
+                (Line::Comment(_), Some(comment)) => write!(f, "{comment}")?,
+                (_, Some(comment)) => write!(f, " {comment}")?,
+                _ => {}
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays a machine as a Graphviz digraph.
+pub struct Dot<'a>(pub &'a TurdFile<'a>);
+
+impl Display for Dot<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let quote = |s: &str| format!("\"{}\"", escape(s));
+        writeln!(f, "digraph turing_machine {{")?;
+        writeln!(f, "    rankdir=LR;")?;
+        for &(state, halt) in &self.0.header.halting {
+            let shape = match halt {
+                Halting::Accept => "doublecircle",
+                Halting::Reject => "doubleoctagon",
+                Halting::Halt => "Msquare",
+            };
+            writeln!(f, "    {} [shape={shape}];", quote(state))?;
+        }
+        for turd in &self.0.turds {
+            // One line of the label per tape.
+            let label = (0..turd.step.len())
+                .map(|i| {
+                    escape(&format!(
+                        "{}/{},{}",
+                        turd.read[i], turd.write[i], turd.step[i]
+                    ))
+                })
+                .collect::<Vec<_>>();
+            writeln!(
+                f,
+                "    {} -> {} [label=\"{}\"];",
+                quote(turd.current),
+                quote(turd.next),
+                label.join("\\n")
+            )?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// One transition line, as written, with any patterns and `$x` variables
+/// left as they are.
+pub struct Turd<'a> {
+    pub(crate) line: usize,
+    pub(crate) text: &'a str,
+    pub(crate) current: State<'a>,
+    pub(crate) read: Vec<Symbol<'a>>,
+    pub(crate) write: Vec<Symbol<'a>>,
+    pub(crate) step: Vec<Step>,
+    pub(crate) next: State<'a>,
+}
+
+impl<'a> Turd<'a> {
+    /// The 1-based line of the transition in its file.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The state the transition leaves.
+    pub fn current(&self) -> &'a str {
+        self.current
+    }
+
+    /// What the transition reads on each tape.
+    pub fn read(&self) -> &[&'a str] {
+        &self.read
+    }
+
+    /// What the transition writes on each tape, `*` leaving the cell as it is.
+    pub fn write(&self) -> &[&'a str] {
+        &self.write
+    }
+
+    /// How the transition moves each head.
+    pub fn step(&self) -> &[Step] {
+        &self.step
+    }
+
+    /// The state the transition enters.
+    pub fn next(&self) -> &'a str {
+        self.next
+    }
+
+    fn parse_turd(s: (usize, &'a str)) -> Result<Self, SpanError<'a>> {
+        let mut tokens = s.1.split_whitespace();
+        if tokens.clone().count() != 5 {
+            return Err(SpanError {
+                span: s.1,
+                message: "A single turd is expected to have 5 tokens".to_string(),
+            });
+        }
+
+        let current = tokens.next().unwrap();
+        let read = tokens.next().unwrap();
+        let write = tokens.next().unwrap();
+        let step = tokens
+            .next()
+            .unwrap()
+            .split(',')
+            .map(|step| {
+                Step::try_from(step).map_err(|e| SpanError {
+                    span: step,
+                    message: e.to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // Symbols are only split into one per tape on multi-tape machines, so
+        // `,` stays usable as a symbol on a single tape.
+        let symbols = |token: &'a str| match step.len() {
+            1 => Ok(vec![token]),
+            tapes => {
+                let symbols = token.split(',').collect::<Vec<_>>();
+                if symbols.len() != tapes {
+                    return Err(SpanError {
+                        span: token,
+                        message: format!("Expected {tapes} comma-separated symbols, one per tape"),
+                    });
+                }
+                Ok(symbols)
+            }
+        };
+        Ok(Self {
+            line: s.0 + 1,
+            text: s.1,
+            current,
+            read: symbols(read)?,
+            write: symbols(write)?,
+            step,
+            next: tokens.next().unwrap(),
+        })
+    }
+
+    // One rule per assignment of symbols from the domain to the turd's
+    // variables, or the turd itself when it has none.
+    pub(crate) fn expand<'t>(&'t self, domain: &[&str]) -> Vec<Rule<'t, 'a>> {
+        let tokens = [self.current, self.next].into_iter();
+        let tokens = tokens.chain(self.read.iter().chain(&self.write).copied());
+        let mut variables = Vec::<&str>::new();
+        for token in tokens {
+            substitute(token, |name| {
+                if !variables.contains(&name) {
+                    variables.push(name);
+                }
+                ""
+            });
+        }
+        // Parsing rejects templates when the domain is empty.
+        if !variables.is_empty() && domain.is_empty() {
+            return Vec::new();
+        }
+        let mut rules = Vec::new();
+        let mut values = vec![0; variables.len()];
+        loop {
+            let value =
+                |name: &str| domain[values[variables.iter().position(|&v| v == name).unwrap()]];
+            let substitute = |token| substitute(token, value);
+            rules.push(Rule {
+                turd: self,
+                current: substitute(self.current),
+                read: self.read.iter().map(|&read| substitute(read)).collect(),
+                write: self.write.iter().map(|&write| substitute(write)).collect(),
+                next: substitute(self.next),
+            });
+            let Some(n) = (0..values.len())
+                .rev()
+                .find(|&n| values[n] + 1 < domain.len())
+            else {
+                break;
+            };
+            values[n] += 1;
+            values[n + 1..].fill(0);
+        }
+        rules
+    }
+}
+
+// A turd with its `$x` variables replaced by symbols.
+pub(crate) struct Rule<'t, 'a> {
+    pub(crate) turd: &'t Turd<'a>,
+    pub(crate) current: String,
+    pub(crate) read: Vec<String>,
+    pub(crate) write: Vec<String>,
+    pub(crate) next: String,
+}
+
+// Replaces each `$` followed by letters or digits in `token` with the value
+// of that variable. A `$` on its own is left alone.
+fn substitute<'t, 'v>(token: &'t str, mut value: impl FnMut(&'t str) -> &'v str) -> String {
+    let mut substituted = String::new();
+    let mut rest = token;
+    while let Some(i) = rest.find('$') {
+        substituted.push_str(&rest[..i + 1]);
+        rest = &rest[i + 1..];
+        let len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        if len > 0 {
+            substituted.pop();
+            substituted.push_str(value(&rest[..len]));
+            rest = &rest[len..];
+        }
+    }
+    substituted.push_str(rest);
+    substituted
+}
+
+fn has_variables(token: &str) -> bool {
+    let mut found = false;
+    substitute(token, |_| {
+        found = true;
+        ""
+    });
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The line and message of every error in `source`.
+    fn errors(source: &str) -> Vec<(usize, String)> {
+        match TurdFile::parse("test.turd", source) {
+            Ok(_) => Vec::new(),
+            Err(TuringMachineError::Parse(diagnostics)) => (diagnostics.iter())
+                .map(|d| (d.line(), d.message().to_string()))
+                .collect(),
+            Err(e) => panic!("unexpected error {e}"),
+        }
+    }
+
+    #[test]
+    fn reports_every_error_with_its_line() {
+        let errors = errors("q0 1 1 R q0\nq0 1 1 R\nq0 1,1 1,1 R,L q1\nq0 1 1 X q1\nblank: _\n");
+        let lines = errors.iter().map(|(line, _)| *line).collect::<Vec<_>>();
+        assert_eq!(lines, [2, 3, 4, 5]);
+        assert_eq!(errors[0].1, "A single turd is expected to have 5 tokens");
+        assert_eq!(
+            errors[1].1,
+            "Expected a transition over 1 tape(s) but this one has 2"
+        );
+        assert_eq!(
+            errors[3].1,
+            "Directives must come before the first transition"
+        );
+    }
+
+    #[test]
+    fn reports_bad_directives() {
+        assert_eq!(
+            errors("tapes: 0\n")[0].1,
+            "0 is not a positive number of tapes"
+        );
+        assert_eq!(
+            errors("frobnicate: 1\n")[0].1,
+            "Unknown directive frobnicate:"
+        );
+        assert_eq!(
+            errors("alphabet: 0 1\nq 2 1 R q\n")[0].1,
+            "2 is not in the declared alphabet"
+        );
+        assert_eq!(
+            errors("accept: yes\nreject: no yes\n"),
+            [(2, "State yes is already declared with accept:".to_string())]
+        );
+    }
+
+    #[test]
+    fn reports_bad_classes() {
+        assert_eq!(
+            errors("q @digits 1 R q\n")[0].1,
+            "No set named digits is declared with set:"
+        );
+        assert_eq!(
+            errors("q [9-0] 1 R q\n")[0].1,
+            "9-0 is not a valid range in [9-0]"
+        );
+    }
+
+    #[test]
+    fn checks_templates_once_expanded() {
+        assert_eq!(
+            errors("set: d 1 2\nq @$x $x R h\n"),
+            [(
+                2,
+                "@$x becomes @1. No set named 1 is declared with set:".to_string()
+            )]
+        );
+        assert_eq!(
+            errors("q [$x] [$x] R h\nq 1 2 R h\n"),
+            [(1, "[$x] becomes [1], which cannot be written".to_string())]
+        );
+        assert_eq!(errors("q $x $x R h\n").len(), 1);
+    }
+
+    #[test]
+    fn expands_each_variable_over_the_domain() {
+        let file = TurdFile::parse("test.turd", "alphabet: a b\nq $x,$y $y,$x R,R q\n").unwrap();
+        let rules = file.rules();
+        let rules = (rules.iter())
+            .map(|rule| (rule.read.join(","), rule.write.join(",")))
+            .collect::<Vec<_>>();
+        let expected = [
+            ("a,a", "a,a"),
+            ("a,b", "b,a"),
+            ("b,a", "a,b"),
+            ("b,b", "b,b"),
+        ];
+        let expected = expected.map(|(read, write)| (read.to_string(), write.to_string()));
+        assert_eq!(rules, expected);
+    }
+
+    #[test]
+    fn domain_leaves_out_the_blank_and_patterns() {
+        let file = TurdFile::parse("test.turd", "set: d 7\nq 1 _ R q\nq [2-3] * R q\n").unwrap();
+        assert_eq!(file.domain(), ["1", "7"]);
+    }
+
+    #[test]
+    fn parses_test_blocks_between_transitions() {
+        let source = "q 1 1 R q\ntest: ones\ntape: ^1 1\nexpect: stuck\n\nq _ _ S q\n";
+        let file = TurdFile::parse("test.turd", source).unwrap();
+        assert_eq!(file.turds().len(), 2);
+        assert_eq!(file.tests()[0].name.as_deref(), Some("ones"));
+        assert_eq!(file.tests()[0].tapes[0].cells, ["1", "1"]);
+        assert_eq!(file.tests()[0].expected.result.as_deref(), Some("stuck"));
+    }
+}